@@ -0,0 +1,29 @@
+use observable_btree::{
+    batch::Batch,
+    model::{Operation, Types},
+    BTree,
+};
+
+#[tokio::main]
+async fn main() {
+    let btree = BTree::start(1000);
+
+    let ins = btree.insert("balance:alice".to_string(), 100).await;
+    assert!(ins.unwrap().is_none());
+    let ins = btree.insert("balance:bob".to_string(), 0).await;
+    assert!(ins.unwrap().is_none());
+
+    // Both operations are applied, or neither of them
+    let transfer = Batch::new()
+        .get_mut("balance:alice".to_string(), -30, Operation::Add)
+        .get_mut("balance:bob".to_string(), 30, Operation::Add);
+    assert_eq!(btree.apply(transfer).await, Ok(()));
+
+    let values = btree.values().await;
+    assert_eq!(
+        values.unwrap(),
+        vec![Types::Integer(70), Types::Integer(30)]
+    );
+
+    print!("Done!")
+}
@@ -0,0 +1,32 @@
+use futures::StreamExt;
+
+use observable_btree::{observer::Event, BTree};
+
+#[tokio::main]
+async fn main() {
+    let btree = BTree::start(1000);
+
+    let changes = btree.changes().await.unwrap();
+
+    tokio::spawn(async move {
+        let ins = btree.insert("hello".to_string(), 5).await;
+        assert!(ins.unwrap().is_none());
+
+        let ins = btree.insert("wow".to_string(), 7).await;
+        assert!(ins.unwrap().is_none());
+    });
+
+    let keys = changes
+        .take(2)
+        .filter_map(|e| async move {
+            match e {
+                Event::Change(change) => Some(change.key),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(keys, vec!["hello".to_string(), "wow".to_string()]);
+
+    print!("Done!")
+}
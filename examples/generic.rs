@@ -0,0 +1,24 @@
+use observable_btree::BTree;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Job {
+    name: String,
+    retries: u8,
+}
+
+#[tokio::main]
+async fn main() {
+    let btree = BTree::<u64, Job>::spawn(1000);
+
+    let job = Job {
+        name: "reindex".to_string(),
+        retries: 0,
+    };
+    let ins = btree.insert(42, job.clone()).await;
+    assert!(ins.unwrap().is_none());
+
+    let get = btree.get(42).await;
+    assert_eq!(get.unwrap(), Some(job));
+
+    print!("Done!")
+}
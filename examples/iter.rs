@@ -0,0 +1,24 @@
+use futures::StreamExt;
+use observable_btree::BTree;
+
+#[tokio::main]
+async fn main() {
+    let btree = BTree::<u64, String>::spawn(1000);
+
+    for i in 0..100 {
+        let ins = btree.insert(i, format!("job {}", i)).await;
+        assert!(ins.unwrap().is_none());
+    }
+
+    // Entries are fetched 10 at a time, other tasks can use the BTree between pages
+    let mut iter = btree.iter_from(50, 10);
+    let mut count = 0;
+    while let Some(entry) = iter.next().await {
+        let (k, v) = entry.unwrap();
+        assert_eq!(v, format!("job {}", k));
+        count += 1;
+    }
+    assert_eq!(count, 50);
+
+    print!("Done!")
+}
@@ -15,10 +15,7 @@ async fn main() {
 
     let remove = btree.remove_entry("wow".to_string()).await;
     let remove_kv = remove.unwrap().unwrap();
-    assert_eq!(
-        remove_kv,
-        Types::KeyValue("wow".to_string(), Box::new(Types::Integer(5)))
-    );
+    assert_eq!(remove_kv, ("wow".to_string(), Types::Integer(5)));
 
     print!("Done!")
 }
@@ -0,0 +1,23 @@
+use observable_btree::{model::Types, BTree};
+
+#[tokio::main]
+async fn main() {
+    let btree = BTree::start(1000);
+
+    let ins = btree.insert("seats".to_string(), 2).await;
+    assert!(ins.unwrap().is_none());
+
+    // The check and the decrement run inside the BTree thread, so two tasks can't take the last seat
+    let book = |x: Option<&mut Types>| match x {
+        Some(Types::Integer(seats)) if *seats > 0 => {
+            *seats -= 1;
+            true
+        }
+        _ => false,
+    };
+    assert!(btree.update("seats".to_string(), book).await.unwrap());
+    assert!(btree.update("seats".to_string(), book).await.unwrap());
+    assert!(!btree.update("seats".to_string(), book).await.unwrap());
+
+    print!("Done!")
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use observable_btree::{model::Types, BTree};
+
+#[tokio::main]
+async fn main() {
+    let btree = BTree::start(1000);
+
+    let btree_async = btree.clone();
+    tokio::spawn(async move {
+        let ins = btree_async.insert("ready".to_string(), true).await;
+        assert!(ins.unwrap().is_none());
+    });
+
+    let ready = btree
+        .wait_for(
+            "ready".to_string(),
+            |v| *v == Types::Boolean(true),
+            Some(Duration::from_secs(1)),
+        )
+        .await;
+    assert_eq!(ready.unwrap(), Types::Boolean(true));
+
+    print!("Done!")
+}
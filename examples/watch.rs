@@ -0,0 +1,36 @@
+use observable_btree::{
+    model::Types,
+    observer::{ChangeKind, Event},
+    BTree,
+};
+
+#[tokio::main]
+async fn main() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree.watch("hello".to_string()).await.unwrap();
+
+    tokio::spawn(async move {
+        let ins = btree.insert("hello".to_string(), 5).await;
+        assert!(ins.unwrap().is_none());
+
+        let remove = btree.remove("hello".to_string()).await;
+        assert!(remove.unwrap().is_some());
+    });
+
+    let event = match watch.recv().await {
+        Some(Event::Change(event)) => event,
+        _ => panic!("expected a change"),
+    };
+    assert_eq!(event.kind, ChangeKind::Insert);
+    assert_eq!(event.new, Some(Types::Integer(5)));
+
+    let event = match watch.recv().await {
+        Some(Event::Change(event)) => event,
+        _ => panic!("expected a change"),
+    };
+    assert_eq!(event.kind, ChangeKind::Remove);
+    assert_eq!(event.old, Some(Types::Integer(5)));
+
+    print!("Done!")
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use crate::logic::{try_apply_with, Registry};
+use crate::model::{Key, Operation, Types, Value};
+use crate::observer::Change;
+use crate::Error;
+
+/// Function applied to a value by a batch `get_mut`, with the `Registry` of the `BTree` applying the batch.
+type ModifyWith<V> = Box<dyn FnOnce(&mut V, &Registry) -> Result<(), Error> + Send>;
+
+enum BatchOp<K, V> {
+    Insert(K, V),
+    Remove(K),
+    GetMut(K, ModifyWith<V>),
+}
+
+/// `Batch` groups `insert`, `remove` and `get_mut` operations to be applied atomically by `BTree::apply`.
+/// Operations are applied in the order they were added and no other action is processed in between.
+/// If any `get_mut` fails, every operation of the batch is rolled back and its error is returned.
+pub struct Batch<K = String, V = Types> {
+    ops: Vec<BatchOp<K, V>>,
+}
+
+impl<K, V> Default for Batch<K, V> {
+    fn default() -> Self {
+        Self { ops: Vec::new() }
+    }
+}
+
+impl<K: Key, V: Value> Batch<K, V> {
+    /// Creates an empty `Batch`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an `insert` of value `v` at key `k` to the batch.
+    pub fn insert<T: Into<V>>(mut self, k: K, v: T) -> Self {
+        self.ops.push(BatchOp::Insert(k, v.into()));
+        self
+    }
+
+    /// Adds a `remove` of key `k` to the batch. Removing a key that does not exist does not fail the batch.
+    pub fn remove(mut self, k: K) -> Self {
+        self.ops.push(BatchOp::Remove(k));
+        self
+    }
+
+    /// Applies every operation to `btree`, returning the `(key, old, new)` changes applied.
+    /// If an operation fails, `btree` is restored and the error of the operation is returned.
+    pub(crate) fn apply(
+        self,
+        btree: &mut BTreeMap<K, V>,
+        operations: &Registry,
+    ) -> Result<Vec<Change<K, V>>, Error> {
+        let mut changes: Vec<Change<K, V>> = Vec::new();
+        let mut failed = Ok(());
+
+        for op in self.ops {
+            match op {
+                BatchOp::Insert(k, v) => {
+                    let old = btree.insert(k.clone(), v.clone());
+                    changes.push((k, old, Some(v)));
+                }
+                BatchOp::Remove(k) => {
+                    if let Some(old) = btree.remove(&k) {
+                        changes.push((k, Some(old), None));
+                    }
+                }
+                BatchOp::GetMut(k, f) => match btree.get_mut(&k) {
+                    Some(x) => {
+                        let old = x.clone();
+                        if let Err(e) = f(x, operations) {
+                            failed = Err(e);
+                            break;
+                        }
+                        changes.push((k, Some(old), Some(x.clone())));
+                    }
+                    None => {
+                        failed = Err(Error::NotFound);
+                        break;
+                    }
+                },
+            }
+        }
+
+        if let Err(e) = failed {
+            for (k, old, _) in changes.into_iter().rev() {
+                match old {
+                    Some(v) => btree.insert(k, v),
+                    None => btree.remove(&k),
+                };
+            }
+            Err(e)
+        } else {
+            Ok(changes)
+        }
+    }
+}
+
+impl<K: Key> Batch<K, Types> {
+    /// Adds a `get_mut` applying `op` with `v` to the value at key `k` to the batch.
+    /// The batch fails with `Error::NotFound` if the key does not exist, or with the error of the operation if it can't be applied.
+    pub fn get_mut<T: Into<Types>>(mut self, k: K, v: T, op: Operation) -> Self {
+        let v: Types = v.into();
+        self.ops.push(BatchOp::GetMut(
+            k,
+            Box::new(move |x, operations| try_apply_with(x, v, op, operations)),
+        ));
+        self
+    }
+}
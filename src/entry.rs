@@ -0,0 +1,88 @@
+use crate::logic::try_apply_with;
+use crate::model::{Key, Operation, Types, Value};
+use crate::{Action, BTree, Error, TryModify};
+
+/// Function creating the value inserted by an `Entry` when its key does not exist.
+pub(crate) type Insert<V> = Box<dyn FnOnce() -> V + Send>;
+
+/// `Entry` is equivalent to [`std::collection::btree_map::Entry`](https://doc.rust-lang.org/std/collections/btree_map/enum.Entry.html),
+/// it is created by `BTree::entry`.
+/// Nothing is sent to the `BTree` until `or_insert`, `or_insert_with` or `or_default` is awaited,
+/// then the whole entry is applied as a single action, so "modify or initialize" can't be interleaved by other tasks.
+pub struct Entry<'a, K, V> {
+    btree: &'a BTree<K, V>,
+    key: K,
+    modify: Option<TryModify<V>>,
+}
+
+impl<'a, K: Key, V: Value> Entry<'a, K, V> {
+    pub(crate) fn new(btree: &'a BTree<K, V>, key: K) -> Self {
+        Self {
+            btree,
+            key,
+            modify: None,
+        }
+    }
+
+    /// Returns the key of this entry.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Method `and_modify` sets a function to be applied to the value if the key already exists.
+    /// If `f` panics, the entry returns `Err(Error::Panicked)` and the `BTree` keeps running.
+    /// Unlike a failing `CustomOperation`, the value is not restored, it keeps any change `f` made before panicking.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut V) + Send + 'static,
+    {
+        self.modify = Some(Box::new(move |v| {
+            f(v);
+            Ok(())
+        }));
+        self
+    }
+
+    /// Method `or_insert` inserts `default` if the key does not exist, else it applies `and_modify`.
+    /// It returns the value at the key after the operation.
+    pub async fn or_insert<T: Into<V>>(self, default: T) -> Result<V, Error> {
+        let default = default.into();
+        self.or_insert_with(move || default).await
+    }
+
+    /// Method `or_insert_with` inserts the result of `default` if the key does not exist, else it applies `and_modify`.
+    /// `default` is only called inside the `BTree` thread when the key does not exist.
+    /// If it panics, nothing is inserted and `Err(Error::Panicked)` is returned.
+    /// It returns the value at the key after the operation.
+    pub async fn or_insert_with<F>(self, default: F) -> Result<V, Error>
+    where
+        F: FnOnce() -> V + Send + 'static,
+    {
+        let Entry { btree, key, modify } = self;
+        let default: Insert<V> = Box::new(default);
+        btree
+            .request(|tx_o| Action::Entry(key, modify, default, tx_o))
+            .await?
+    }
+
+    /// Method `or_default` inserts `V::default()` if the key does not exist, else it applies `and_modify`.
+    /// It returns the value at the key after the operation.
+    pub async fn or_default(self) -> Result<V, Error>
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default).await
+    }
+}
+
+impl<'a, K: Key> Entry<'a, K, Types> {
+    /// Method `and_apply` sets an `Operation` with `v` to be applied to the value if the key already exists,
+    /// like `btree.entry(k).and_apply(1, Operation::Add).or_insert(1).await` to increment or initialize a counter.
+    /// If the operation fails, like with `Error::Overflow` or `Error::Unsupported`, the value is unchanged and the entry returns the error.
+    pub fn and_apply<T: Into<Types>>(mut self, v: T, op: Operation) -> Self {
+        let v: Types = v.into();
+        let operations = self.btree.operations.clone();
+        self.modify = Some(Box::new(move |x| try_apply_with(x, v, op, &operations)));
+        self
+    }
+}
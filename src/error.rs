@@ -0,0 +1,74 @@
+use std::{convert::Infallible, fmt};
+
+/// `Error` is returned by every `BTree` method and by the `TryInto` conversions of `Types`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The `BTree` thread is not running, so the action could not be sent.
+    ActorStopped,
+    /// The `BTree` thread dropped the reply channel before answering.
+    ReplyDropped,
+    /// A `Types` value was not the expected variant.
+    TypeMismatch {
+        expected: &'static str,
+        actual: &'static str,
+    },
+    /// The operation cannot be applied to a value of type `actual`.
+    Unsupported {
+        operation: &'static str,
+        actual: &'static str,
+    },
+    /// The integer operation overflowed, the value was not modified.
+    Overflow { operation: &'static str },
+    /// An integer value was divided by zero, the value was not modified.
+    DivisionByZero,
+    /// The index is not inside the collection, the value was not modified.
+    IndexOutOfBounds { index: usize, len: usize },
+    /// The element or key to remove is not in the collection, the value was not modified.
+    NotFound,
+    /// No `CustomOperation` is registered with this name.
+    UnknownOperation(String),
+    /// A `CustomOperation` failed for the reason it describes, the value was not modified.
+    Custom(String),
+    /// A closure passed to the `BTree` panicked inside its thread, the `BTree` keeps running.
+    Panicked,
+    /// The operation did not complete in time.
+    Timeout,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ActorStopped => write!(f, "the BTree thread is not running"),
+            Error::ReplyDropped => write!(f, "the BTree thread dropped the reply"),
+            Error::TypeMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "type mismatch, expected {} but found {}",
+                    expected, actual
+                )
+            }
+            Error::Unsupported { operation, actual } => {
+                write!(f, "operation {} is not supported for {}", operation, actual)
+            }
+            Error::Overflow { operation } => write!(f, "operation {} overflowed", operation),
+            Error::DivisionByZero => write!(f, "division by zero"),
+            Error::IndexOutOfBounds { index, len } => {
+                write!(f, "index {} is out of bounds for length {}", index, len)
+            }
+            Error::NotFound => write!(f, "element not found in the collection"),
+            Error::UnknownOperation(name) => write!(f, "unknown operation {}", name),
+            Error::Custom(reason) => write!(f, "custom operation failed, {}", reason),
+            Error::Panicked => write!(f, "a closure panicked inside the BTree thread"),
+            Error::Timeout => write!(f, "operation timed out"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+// lets `Types` be converted into collections of `Types`, which can't fail.
+impl From<Infallible> for Error {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::VecDeque,
+    future::{poll_fn, Future},
+    ops::Bound,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+
+use crate::model::{Key, Value};
+use crate::{Action, BTree, Error};
+
+type Page<K, V> = Result<Vec<(K, V)>, Error>;
+// the handle is moved into the request and given back with the page, so fetching a page doesn't clone it.
+type PageFuture<K, V> = Pin<Box<dyn Future<Output = (BTree<K, V>, Page<K, V>)> + Send>>;
+
+/// `Iter` is a cursor over the entries of a `BTree` sorted by key, it is created by `BTree::iter` and `BTree::iter_from`.
+/// Entries are fetched lazily, `page_size` at a time, so a large scan never blocks the `BTree` thread for longer than a page.
+/// Each page is read in a single action, but changes applied between pages are visible to the next pages.
+/// `Iter` keeps a `BTree` handle, so the `BTree` keeps running while it is alive and it is counted by `BTree::handle_count`.
+pub struct Iter<K, V> {
+    btree: Option<BTree<K, V>>,
+    start: Bound<K>,
+    page_size: usize,
+    page: VecDeque<(K, V)>,
+    pending: Option<PageFuture<K, V>>,
+    done: bool,
+}
+
+impl<K: Key, V: Value> Iter<K, V> {
+    pub(crate) fn new(btree: BTree<K, V>, start: Bound<K>, page_size: usize) -> Self {
+        Self {
+            btree: Some(btree),
+            start,
+            page_size: page_size.max(1),
+            page: VecDeque::new(),
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Method `next_page` returns the next entries, at most `page_size` of them.
+    /// It returns `Ok(None)` once every entry was returned.
+    pub async fn next_page(&mut self) -> Result<Option<Vec<(K, V)>>, Error> {
+        if !self.page.is_empty() {
+            return Ok(Some(self.page.drain(..).collect()));
+        }
+        poll_fn(|cx| self.poll_page(cx)).await.transpose()
+    }
+
+    fn poll_page(&mut self, cx: &mut Context<'_>) -> Poll<Option<Page<K, V>>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        if let Some(btree) = self.btree.take() {
+            let start = self.start.clone();
+            let page_size = self.page_size;
+            self.pending = Some(Box::pin(async move {
+                let page = btree
+                    .request(|tx_o| Action::Page(start, page_size, tx_o))
+                    .await;
+                (btree, page)
+            }));
+        }
+
+        let (btree, result) = match self.pending.as_mut() {
+            Some(pending) => std::task::ready!(pending.as_mut().poll(cx)),
+            None => return Poll::Ready(None),
+        };
+        self.btree = Some(btree);
+        self.pending = None;
+
+        match result {
+            Ok(page) => {
+                // a page shorter than `page_size` means there are no entries after it.
+                self.done = page.len() < self.page_size;
+                match page.last() {
+                    Some((k, _)) => self.start = Bound::Excluded(k.clone()),
+                    None => return Poll::Ready(None),
+                }
+                Poll::Ready(Some(Ok(page)))
+            }
+            Err(e) => {
+                self.done = true;
+                Poll::Ready(Some(Err(e)))
+            }
+        }
+    }
+}
+
+// `Iter` never pins its fields, the pending page is already boxed.
+impl<K, V> Unpin for Iter<K, V> {}
+
+impl<K: Key, V: Value> Stream for Iter<K, V> {
+    type Item = Result<(K, V), Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(entry) = self.page.pop_front() {
+                return Poll::Ready(Some(Ok(entry)));
+            }
+            match std::task::ready!(self.poll_page(cx)) {
+                Some(Ok(page)) => self.page = page.into(),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
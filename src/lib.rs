@@ -1,311 +1,1052 @@
-use std::{collections::BTreeMap, convert::TryInto};
+use std::{
+    collections::BTreeMap,
+    ops::{Bound, RangeBounds},
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::Duration,
+};
 
 use tokio::sync::mpsc::{self, Sender};
-use tokio::sync::oneshot;
+use tokio::sync::{oneshot, watch};
 
+pub mod batch;
+pub mod entry;
+mod error;
+pub mod iter;
 pub mod logic;
 pub mod model;
+pub mod observer;
+pub mod path;
 
-use logic::add;
-use model::{Operation, Types};
-
-enum Action {
-    Insert(String, Types),
-    Contains(String),
-    Get(String),
-    GetMut(String, Types, Operation),
-    Len,
-    Keys,
-    Values,
-    Remove(String),
-    RemoveEntry(String),
+pub use error::Error;
+
+use batch::Batch;
+use entry::{Entry, Insert};
+use iter::Iter;
+use logic::{try_apply_custom, try_apply_with, Registry};
+use model::{Key, Operation, Types, Value};
+use observer::{Backpressure, Change, Event, KeyFilter, Observer, Observers, Subscription};
+use path::Path;
+
+/// Buffer size used when a `BTree` is created with `From<BTreeMap>`.
+pub const DEFAULT_BUFFER_SIZE: usize = 1000;
+
+/// Function applied to a value inside the `BTree` thread, it returns `true` if the value was modified.
+type Modify<V> = Box<dyn FnOnce(&mut V) -> bool + Send>;
+
+/// Function applied to a value inside the `BTree` thread, it fails if the value can't be modified.
+type TryModify<V> = Box<dyn FnOnce(&mut V) -> Result<(), Error> + Send>;
+
+/// Function reading the value of a key inside the `BTree` thread, it sends its own result.
+type Read<V> = Box<dyn FnOnce(Option<&V>) + Send>;
+
+/// Function updating the value of a key inside the `BTree` thread, it sends its own result
+/// and returns `true` if the value was modified. The second argument is a copy of the value before the update,
+/// only made when a `Subscription` watches the key.
+type Update<V> = Box<dyn FnOnce(Option<&mut V>, Option<&V>) -> bool + Send>;
+
+/// Function checking the current value of a key inside the `BTree` thread.
+type Check<V> = Box<dyn FnOnce(Option<&V>) -> bool + Send>;
+
+/// Function deciding inside the `BTree` thread if an entry is kept by `retain`.
+type Retain<K, V> = Box<dyn FnMut(&K, &V) -> bool + Send>;
+
+/// Returns the bounds of the keys starting with `prefix`.
+pub(crate) fn prefix_range(prefix: &str) -> (Bound<String>, Bound<String>) {
+    let start = Bound::Included(prefix.to_owned());
+    // the first string after every key starting with `prefix` is `prefix` with its last char incremented.
+    let mut end = prefix.to_owned();
+    while let Some(last) = end.pop() {
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(std::char::from_u32);
+        if let Some(next) = next {
+            end.push(next);
+            return (start, Bound::Excluded(end));
+        }
+    }
+    (start, Bound::Unbounded)
+}
+
+/// Runs a closure given by the user inside the `BTree` thread, returning `Error::Panicked` if it panics
+/// instead of stopping the thread.
+pub(crate) fn catch_panic<R>(f: impl FnOnce() -> R) -> Result<R, Error> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| Error::Panicked)
+}
+
+/// Checks if `start` and `end` form a range accepted by `BTreeMap::range`, which panics otherwise.
+fn is_valid_range<K: Ord>(start: &Bound<K>, end: &Bound<K>) -> bool {
+    match (start, end) {
+        (Bound::Excluded(s), Bound::Excluded(e)) => s < e,
+        (Bound::Included(s), Bound::Included(e))
+        | (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e)) => s <= e,
+        _ => true,
+    }
+}
+
+enum Action<K, V> {
+    Insert(K, V, oneshot::Sender<Option<V>>),
+    Contains(K, oneshot::Sender<bool>),
+    Get(K, oneshot::Sender<Option<V>>),
+    GetMut(K, Modify<V>, oneshot::Sender<bool>),
+    Read(K, Read<V>),
+    Update(K, Update<V>),
+    GetMutValues(
+        K,
+        TryModify<V>,
+        oneshot::Sender<Option<Result<(V, V), Error>>>,
+    ),
+    Len(oneshot::Sender<usize>),
+    Keys(oneshot::Sender<Vec<K>>),
+    Values(oneshot::Sender<Vec<V>>),
+    Range(Bound<K>, Bound<K>, oneshot::Sender<Vec<(K, V)>>),
+    Page(Bound<K>, usize, oneshot::Sender<Vec<(K, V)>>),
+    First(Bound<K>, oneshot::Sender<Option<(K, V)>>),
+    Last(Bound<K>, oneshot::Sender<Option<(K, V)>>),
+    PopFirst(oneshot::Sender<Option<(K, V)>>),
+    PopLast(oneshot::Sender<Option<(K, V)>>),
+    Remove(K, oneshot::Sender<Option<V>>),
+    RemoveEntry(K, oneshot::Sender<Option<(K, V)>>),
+    Entry(
+        K,
+        Option<TryModify<V>>,
+        Insert<V>,
+        oneshot::Sender<Result<V, Error>>,
+    ),
+    CompareAndSwap(K, Check<V>, Option<V>, oneshot::Sender<(bool, Option<V>)>),
+    Batch(Batch<K, V>, oneshot::Sender<Result<(), Error>>),
+    Clear(oneshot::Sender<()>),
+    Extend(Vec<(K, V)>, oneshot::Sender<()>),
+    Retain(Retain<K, V>, oneshot::Sender<Result<(), Error>>),
+    DrainRange(Bound<K>, Bound<K>, oneshot::Sender<Vec<(K, V)>>),
+    Watch(Observer<K, V>, oneshot::Sender<()>),
+    Snapshot(oneshot::Sender<BTreeMap<K, V>>),
+    Shutdown(Option<oneshot::Sender<BTreeMap<K, V>>>),
 }
 
 /// `BTree` is where the information `Sender` is contained.
-/// Its inner implementation has a tuple containing the action to be taken as well as a oneshot channel to receive data.
+/// Its inner implementation sends the action to be taken, containing a oneshot channel to receive data.
 /// To start the `BTree` thread just execute `BTree::start(buffer_size: usize)`. If you `buffer_size` is too short
 /// it may cause synchronization problems, so it should be well ajusted to your application needs.
-pub struct BTree {
-    tx: Sender<(Action, tokio::sync::oneshot::Sender<Option<Types>>)>,
+///
+/// `BTree` is generic over its keys `K` and values `V`, by default it is a `BTree<String, Types>`.
+/// To store other types use `BTree::<K, V>::spawn(buffer_size: usize)`.
+///
+/// `BTree` is `Clone`, `Send` and `Sync`. Cloning it creates a new handle to the same thread, so it can be shared
+/// between tasks without an `Arc<Mutex<_>>`. The thread stops when the last handle is dropped.
+pub struct BTree<K = String, V = Types> {
+    tx: Sender<Action<K, V>>,
+    exit: watch::Receiver<()>,
+    operations: Arc<Registry>,
+}
+
+impl<K, V> Clone for BTree<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            exit: self.exit.clone(),
+            operations: self.operations.clone(),
+        }
+    }
 }
 
 impl BTree {
     /// `BTree::start(buffer_size: usize)` is the entrypoint to start using `BTree` methods.
     /// It creates a thread containing the BTreeMap and keeps listening to entries.
+    /// The thread exits after `shutdown` is called or when the `BTree` is dropped.
+    /// It is the same as `BTree::spawn` for the default `BTree<String, Types>`.
     pub fn start(buffer_size: usize) -> Self {
-        let (tx, mut rx) = mpsc::channel(buffer_size);
+        Self::spawn(buffer_size)
+    }
+
+    /// `BTree::start_with(map, buffer_size)` is the same as `BTree::start`, but the thread begins with the entries of `map`.
+    /// It is the same as `BTree::spawn_with` for the default `BTree<String, Types>`.
+    pub fn start_with(map: BTreeMap<String, Types>, buffer_size: usize) -> Self {
+        Self::spawn_with(map, buffer_size)
+    }
+
+    /// `BTree::start_with_operations(operations, buffer_size)` is the same as `BTree::start`,
+    /// but `Operation::Custom` can apply the `CustomOperation`s of `operations`.
+    pub fn start_with_operations(operations: Registry, buffer_size: usize) -> Self {
+        Self::spawn_with_operations(BTreeMap::new(), operations, buffer_size)
+    }
+}
+
+impl<K: Key, V: Value> BTree<K, V> {
+    /// `BTree::spawn(buffer_size: usize)` starts a `BTree` with keys of type `K` and values of type `V`,
+    /// like `BTree::<u64, MyStruct>::spawn(1000)`.
+    /// It creates a thread containing the BTreeMap and keeps listening to entries.
+    /// The thread exits after `shutdown` is called or when the `BTree` is dropped.
+    pub fn spawn(buffer_size: usize) -> Self {
+        Self::spawn_with(BTreeMap::new(), buffer_size)
+    }
+
+    /// `BTree::spawn_with(map, buffer_size)` is the same as `BTree::spawn`, but the thread begins with the entries of `map`.
+    /// No event is emitted for the initial entries.
+    pub fn spawn_with(map: BTreeMap<K, V>, buffer_size: usize) -> Self {
+        Self::spawn_with_registry(map, Registry::default(), buffer_size)
+    }
+
+    fn spawn_with_registry(map: BTreeMap<K, V>, operations: Registry, buffer_size: usize) -> Self {
+        let operations = Arc::new(operations);
+        let registry = operations.clone();
+        let (tx, mut rx) = mpsc::channel::<Action<K, V>>(buffer_size);
+        let (exit_tx, exit_rx) = watch::channel(());
         tokio::spawn(async move {
-            let mut btree: BTreeMap<String, Types> = BTreeMap::new();
-            loop {
-                if let Some((action, tx_o)) = rx.recv().await {
-                    let tx_o: tokio::sync::oneshot::Sender<Option<Types>> = tx_o;
-                    match action {
-                        Action::Insert(k, v) => {
-                            let insert = btree.insert(k, v);
-                            if let Err(_) = tx_o.send(insert) {
-                                println!("the receiver dropped, mpsc insert");
+            let mut btree: BTreeMap<K, V> = map;
+            let mut observers = Observers::default();
+            let mut inner = None;
+            while let Some(action) = rx.recv().await {
+                match action {
+                    Action::Insert(k, v, tx_o) => {
+                        let insert = btree.insert(k.clone(), v.clone());
+                        observers.notify(&k, insert.clone(), Some(v)).await;
+                        if tx_o.send(insert).is_err() {
+                            println!("the receiver dropped, mpsc insert");
+                        }
+                    }
+                    Action::Contains(k, tx_o) => {
+                        let contains = btree.contains_key(&k);
+                        if tx_o.send(contains).is_err() {
+                            println!("the receiver dropped, mpsc contains");
+                        }
+                    }
+                    Action::GetMut(k, f, tx_o) => {
+                        let get = if let Some(x) = btree.get_mut(&k) {
+                            let old = observers.is_watched(&k).then(|| x.clone());
+                            let modified = f(x);
+                            if let (true, Some(old)) = (modified, old) {
+                                observers.notify(&k, Some(old), Some(x.clone())).await;
                             }
+                            modified
+                        } else {
+                            false
+                        };
+                        if tx_o.send(get).is_err() {
+                            println!("the receiver dropped, mpsc get mut");
                         }
-                        Action::Contains(k) => {
-                            let contains = btree.contains_key(&k);
-                            if let Err(_) = tx_o.send(Some(Types::Boolean(contains))) {
-                                println!("the receiver dropped, mpsc contains k: {}", k);
+                    }
+                    Action::Read(k, f) => f(btree.get(&k)),
+                    Action::Update(k, f) => {
+                        if let Some(x) = btree.get_mut(&k) {
+                            let old = observers.is_watched(&k).then(|| x.clone());
+                            let modified = f(Some(x), old.as_ref());
+                            if let (true, Some(old)) = (modified, old) {
+                                observers.notify(&k, Some(old), Some(x.clone())).await;
                             }
+                        } else {
+                            f(None, None);
                         }
-                        Action::GetMut(key, value, f) => {
-                            let get = if let Some(x) = btree.get_mut(&key) {
-                                match f {
-                                    Operation::Replace => {
-                                        *x = value;
-                                        Some(Types::Boolean(true))
-                                    }
-                                    Operation::Add => add(x, value),
+                    }
+                    Action::GetMutValues(k, f, tx_o) => {
+                        let get = if let Some(x) = btree.get_mut(&k) {
+                            let old = x.clone();
+                            let modified = f(x).map(|_| (old, x.clone()));
+                            if let Ok((old, new)) = &modified {
+                                if observers.is_watched(&k) {
+                                    observers
+                                        .notify(&k, Some(old.clone()), Some(new.clone()))
+                                        .await;
                                 }
-                            } else {
-                                None
-                            };
-                            if let Err(_) = tx_o.send(get) {
-                                println!("the receiver dropped, mpsc get mut k: {}", key);
                             }
+                            Some(modified)
+                        } else {
+                            None
+                        };
+                        if tx_o.send(get).is_err() {
+                            println!("the receiver dropped, mpsc get mut values");
+                        }
+                    }
+                    Action::Get(k, tx_o) => {
+                        let get = btree.get(&k).map(|v| v.to_owned());
+                        if tx_o.send(get).is_err() {
+                            println!("the receiver dropped, mpsc get");
+                        }
+                    }
+                    Action::Keys(tx_o) => {
+                        let keys: Vec<K> = btree.keys().map(|k| k.to_owned()).collect();
+                        if tx_o.send(keys).is_err() {
+                            println!("the receiver dropped, mpsc get keys");
+                        }
+                    }
+                    Action::Values(tx_o) => {
+                        let values: Vec<V> = btree.values().map(|v| v.to_owned()).collect();
+                        if tx_o.send(values).is_err() {
+                            println!("the receiver dropped, mpsc get values");
                         }
-                        Action::Get(k) => {
-                            let get = btree.get(&k);
-                            let get = if let Some(types) = get {
-                                Some(types.to_owned())
+                    }
+                    Action::Range(start, end, tx_o) => {
+                        let range: Vec<(K, V)> = if is_valid_range(&start, &end) {
+                            btree
+                                .range((start, end))
+                                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                                .collect()
+                        } else {
+                            Vec::new()
+                        };
+                        if tx_o.send(range).is_err() {
+                            println!("the receiver dropped, mpsc range");
+                        }
+                    }
+                    Action::Page(start, page_size, tx_o) => {
+                        let page: Vec<(K, V)> = btree
+                            .range((start, Bound::Unbounded))
+                            .take(page_size)
+                            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                            .collect();
+                        if tx_o.send(page).is_err() {
+                            println!("the receiver dropped, mpsc page");
+                        }
+                    }
+                    Action::First(start, tx_o) => {
+                        let first = btree
+                            .range((start, Bound::Unbounded))
+                            .next()
+                            .map(|(k, v)| (k.to_owned(), v.to_owned()));
+                        if tx_o.send(first).is_err() {
+                            println!("the receiver dropped, mpsc first");
+                        }
+                    }
+                    Action::Last(end, tx_o) => {
+                        let last = btree
+                            .range((Bound::Unbounded, end))
+                            .next_back()
+                            .map(|(k, v)| (k.to_owned(), v.to_owned()));
+                        if tx_o.send(last).is_err() {
+                            println!("the receiver dropped, mpsc last");
+                        }
+                    }
+                    Action::Snapshot(tx_o) => {
+                        if tx_o.send(btree.clone()).is_err() {
+                            println!("the receiver dropped, mpsc snapshot");
+                        }
+                    }
+                    Action::Len(tx_o) => {
+                        if tx_o.send(btree.len()).is_err() {
+                            println!("the receiver dropped, mpsc len");
+                        }
+                    }
+                    Action::Remove(k, tx_o) => {
+                        let remove = btree.remove(&k);
+                        if remove.is_some() {
+                            observers.notify(&k, remove.clone(), None).await;
+                        }
+                        if tx_o.send(remove).is_err() {
+                            println!("the receiver dropped, mpsc remove");
+                        }
+                    }
+                    Action::RemoveEntry(k, tx_o) => {
+                        let remove = btree.remove_entry(&k);
+                        if let Some((key, value)) = &remove {
+                            observers.notify(key, Some(value.clone()), None).await;
+                        }
+                        if tx_o.send(remove).is_err() {
+                            println!("the receiver dropped, mpsc remove_entry");
+                        }
+                    }
+                    Action::PopFirst(tx_o) => {
+                        let pop = btree.pop_first();
+                        if let Some((key, value)) = &pop {
+                            observers.notify(key, Some(value.clone()), None).await;
+                        }
+                        if tx_o.send(pop).is_err() {
+                            println!("the receiver dropped, mpsc pop_first");
+                        }
+                    }
+                    Action::PopLast(tx_o) => {
+                        let pop = btree.pop_last();
+                        if let Some((key, value)) = &pop {
+                            observers.notify(key, Some(value.clone()), None).await;
+                        }
+                        if tx_o.send(pop).is_err() {
+                            println!("the receiver dropped, mpsc pop_last");
+                        }
+                    }
+                    Action::Entry(k, modify, default, tx_o) => {
+                        let value = if let Some(x) = btree.get_mut(&k) {
+                            if let Some(f) = modify {
+                                let old = observers.is_watched(&k).then(|| x.clone());
+                                let modified = catch_panic(|| f(x));
+                                // a panicking `f` may have modified the value before panicking, so it is notified as well.
+                                if let (Ok(Ok(())) | Err(_), Some(old)) = (&modified, old) {
+                                    observers.notify(&k, Some(old), Some(x.clone())).await;
+                                }
+                                modified.and_then(|modified| modified).map(|_| x.clone())
                             } else {
-                                None
-                            };
-                            if let Err(_) = tx_o.send(get) {
-                                println!("the receiver dropped, mpsc get k: {}", k);
+                                Ok(x.clone())
                             }
-                        }
-                        Action::Keys => {
-                            let get = btree.keys();
-                            let keys: Vec<Types> = get.map(|k| k.to_owned().into()).collect();
-
-                            if let Err(_) = tx_o.send(Some(Types::Vector(keys))) {
-                                println!("the receiver dropped, mpsc get keys");
+                        } else {
+                            match catch_panic(default) {
+                                Ok(v) => {
+                                    btree.insert(k.clone(), v.clone());
+                                    observers.notify(&k, None, Some(v.clone())).await;
+                                    Ok(v)
+                                }
+                                Err(e) => Err(e),
                             }
+                        };
+                        if tx_o.send(value).is_err() {
+                            println!("the receiver dropped, mpsc entry");
                         }
-                        Action::Values => {
-                            let get = btree.values();
-                            let values: Vec<Types> = get.map(|k| k.to_owned()).collect();
-
-                            if let Err(_) = tx_o.send(Some(Types::Vector(values))) {
-                                println!("the receiver dropped, mpsc get values");
+                    }
+                    Action::CompareAndSwap(k, expected, new, tx_o) => {
+                        let swapped = expected(btree.get(&k));
+                        if swapped {
+                            let old = match &new {
+                                Some(v) => btree.insert(k.clone(), v.clone()),
+                                None => btree.remove(&k),
+                            };
+                            if old.is_some() || new.is_some() {
+                                observers.notify(&k, old, new.clone()).await;
                             }
                         }
-                        Action::Len => {
-                            let len = btree.len();
-                            if let Err(_) = tx_o.send(Some(Types::UInteger(len))) {
-                                println!("the receiver dropped, mpsc len");
-                            }
+                        let current = btree.get(&k).map(|v| v.to_owned());
+                        if tx_o.send((swapped, current)).is_err() {
+                            println!("the receiver dropped, mpsc compare and swap");
                         }
-                        Action::Remove(k) => {
-                            let remove = btree.remove(&k);
-
-                            if let Err(_) = tx_o.send(remove) {
-                                println!("the receiver dropped, mpsc remove for key: {}", k);
+                    }
+                    Action::Batch(batch, tx_o) => {
+                        let applied = match batch.apply(&mut btree, &registry) {
+                            Ok(changes) => {
+                                observers.notify_batch(changes).await;
+                                Ok(())
                             }
+                            Err(e) => Err(e),
+                        };
+                        if tx_o.send(applied).is_err() {
+                            println!("the receiver dropped, mpsc batch");
+                        }
+                    }
+                    Action::Clear(tx_o) => {
+                        let changes: Vec<Change<K, V>> = std::mem::take(&mut btree)
+                            .into_iter()
+                            .filter(|(k, _)| observers.is_watched(k))
+                            .map(|(k, v)| (k, Some(v), None))
+                            .collect();
+                        observers.notify_batch(changes).await;
+                        if tx_o.send(()).is_err() {
+                            println!("the receiver dropped, mpsc clear");
                         }
-                        Action::RemoveEntry(k) => {
-                            let remove = btree.remove_entry(&k);
-                            let key_val = if let Some((key, value)) = remove {
-                                Some(Types::KeyValue(key, Box::new(value)))
+                    }
+                    Action::Extend(entries, tx_o) => {
+                        let mut changes: Vec<Change<K, V>> = Vec::new();
+                        for (k, v) in entries {
+                            if observers.is_watched(&k) {
+                                let old = btree.insert(k.clone(), v.clone());
+                                changes.push((k, old, Some(v)));
                             } else {
-                                None
-                            };
-                            if let Err(_) = tx_o.send(key_val) {
-                                println!("the receiver dropped, mpsc remove_entry for key: {}", k);
+                                btree.insert(k, v);
+                            }
+                        }
+                        observers.notify_batch(changes).await;
+                        if tx_o.send(()).is_err() {
+                            println!("the receiver dropped, mpsc extend");
+                        }
+                    }
+                    Action::Retain(mut f, tx_o) => {
+                        let mut changes: Vec<Change<K, V>> = Vec::new();
+                        let mut retain = Ok(());
+                        btree.retain(|k, v| {
+                            if retain.is_err() {
+                                return true;
+                            }
+                            match catch_panic(|| f(k, v)) {
+                                Ok(true) => true,
+                                Ok(false) => {
+                                    if observers.is_watched(k) {
+                                        changes.push((k.clone(), Some(v.clone()), None));
+                                    }
+                                    false
+                                }
+                                Err(e) => {
+                                    retain = Err(e);
+                                    true
+                                }
                             }
+                        });
+                        observers.notify_batch(changes).await;
+                        if tx_o.send(retain).is_err() {
+                            println!("the receiver dropped, mpsc retain");
                         }
                     }
+                    Action::DrainRange(start, end, tx_o) => {
+                        let keys: Vec<K> = if is_valid_range(&start, &end) {
+                            btree.range((start, end)).map(|(k, _)| k.clone()).collect()
+                        } else {
+                            Vec::new()
+                        };
+                        let drained: Vec<(K, V)> = keys
+                            .into_iter()
+                            .filter_map(|k| btree.remove_entry(&k))
+                            .collect();
+                        let changes: Vec<Change<K, V>> = drained
+                            .iter()
+                            .filter(|(k, _)| observers.is_watched(k))
+                            .map(|(k, v)| (k.clone(), Some(v.clone()), None))
+                            .collect();
+                        observers.notify_batch(changes).await;
+                        if tx_o.send(drained).is_err() {
+                            println!("the receiver dropped, mpsc drain_range");
+                        }
+                    }
+                    Action::Watch(observer, tx_o) => {
+                        observers.watch(observer);
+                        if tx_o.send(()).is_err() {
+                            println!("the receiver dropped, mpsc watch");
+                        }
+                    }
+                    Action::Shutdown(tx_o) => {
+                        if inner.is_none() {
+                            inner = tx_o;
+                        }
+                        rx.close();
+                    }
+                }
+            }
+            observers.shutdown();
+            if let Some(tx_o) = inner {
+                if tx_o.send(btree).is_err() {
+                    println!("the receiver dropped, mpsc into_inner");
                 }
             }
+            drop(exit_tx);
         });
 
-        Self { tx }
+        Self {
+            tx,
+            exit: exit_rx,
+            operations,
+        }
     }
 
-    /// Method `insert` is equivalent to [`std::collection::BTreeMap insert`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.insert),
-    /// it returns `None` if the key does not exist and it returns `Some(Types::_)` with the previous value,
-    /// if the key already exists.
-    pub async fn insert<V: Into<Types>>(&self, k: String, v: V) -> Result<Option<Types>, String> {
-        let v = v.into();
-        let tx = self.tx.clone();
+    async fn request<R>(
+        &self,
+        action: impl FnOnce(oneshot::Sender<R>) -> Action<K, V>,
+    ) -> Result<R, Error> {
         let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::Insert(k.clone(), v.clone());
-        let send = (action, tx_o);
 
-        tx.send(send)
+        self.tx
+            .send(action(tx_o))
             .await
-            .map_err(|_| format!("receiver dropped, insert key {}, value {:?}", k, v))?;
+            .map_err(|_| Error::ActorStopped)?;
+
+        rx_o.await.map_err(|_| Error::ReplyDropped)
+    }
+
+    /// Method `handle_count` returns how many `BTree` handles, the original and its clones, are still alive.
+    /// Every live `Iter` holds a handle, so it is counted as well.
+    pub fn handle_count(&self) -> usize {
+        self.tx.strong_count()
+    }
 
-        rx_o.await
-            .map_err(|_| format!("insert failed {}, value {:?}", k, v))
+    /// Method `insert` is equivalent to [`std::collection::BTreeMap insert`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.insert),
+    /// it returns `None` if the key does not exist and it returns `Some(V)` with the previous value,
+    /// if the key already exists.
+    pub async fn insert<T: Into<V>>(&self, k: K, v: T) -> Result<Option<V>, Error> {
+        let v = v.into();
+        self.request(|tx_o| Action::Insert(k, v, tx_o)).await
     }
 
     /// Method `contains` is equivalent to [`std::collection::BTreeMap contains_key`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.contains_key),
     /// It checks if a key already exists in the `BTree`. If the key exists the return is `Ok(true)`,
     /// if it doesn't exist it returns `Ok(false)`
-    pub async fn contains(&self, k: String) -> Result<bool, String> {
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::Contains(k.clone());
-        let send = (action, tx_o);
-
-        tx.send(send)
-            .await
-            .map_err(|_| format!("receiver dropped, contains key {}", k))?;
-        match rx_o.await {
-            Ok(Some(Types::Boolean(true))) => Ok(true),
-            Err(e) => Err(format!("{:?}", e)),
-            _ => Ok(false),
-        }
+    pub async fn contains(&self, k: K) -> Result<bool, Error> {
+        self.request(|tx_o| Action::Contains(k, tx_o)).await
     }
 
     /// Method `get` is equivalent to [`std::collection::BTreeMap get`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.get),
     /// It returns the value contained at the key passed as argument. If no key is found the return is `Ok(None)`,
-    /// else it returns `Ok(Some(Types::_))`.
-    pub async fn get(&self, k: String) -> Result<Option<Types>, String> {
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::Get(k.clone());
-        let send = (action, tx_o);
+    /// else it returns `Ok(Some(V))`.
+    pub async fn get(&self, k: K) -> Result<Option<V>, Error> {
+        self.request(|tx_o| Action::Get(k, tx_o)).await
+    }
 
-        tx.send(send)
-            .await
-            .map_err(|_| format!("receiver dropped, get key {}", k))?;
+    /// Method `len` is equivalent to [`std::collection::BTreeMap len`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.len),
+    /// It returns the length of the btree as a usize.
+    pub async fn len(&self) -> Result<usize, Error> {
+        self.request(Action::Len).await
+    }
 
-        match rx_o.await {
-            Ok(types) => Ok(types),
-            Err(e) => Err(format!("get failed {} with error: {:?}", k, e)),
-        }
+    /// Method `is_empty` is equivalent to [`std::collection::BTreeMap is_empty`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.is_empty),
+    /// It returns `true` if the btree contains no elements.
+    pub async fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len().await? == 0)
     }
 
-    /// Method `get_mut` is equivalent to [`std::collection::BTreeMap get_mut`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.get_mut),
-    /// It applies an `Operation` to the value obtained and returns true if the operation succeeded or false if it failed.
-    pub async fn get_mut<V: Into<Types>>(
-        &self,
-        k: String,
-        v: V,
-        op: Operation,
-    ) -> Result<bool, String> {
-        let v: Types = v.into();
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::GetMut(k.clone(), v, op);
-        let send = (action, tx_o);
+    /// Method `keys` is equivalent to [`std::collection::BTreeMap keys`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.keys),
+    /// It returns a vector containing all the keys sorted.
+    pub async fn keys(&self) -> Result<Vec<K>, Error> {
+        self.request(Action::Keys).await
+    }
 
-        tx.send(send)
-            .await
-            .map_err(|_| format!("receiver dropped, get mut key {}", k))?;
+    /// Method `values` is equivalent to [`std::collection::BTreeMap values`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.values),
+    /// It returns a vector containing all the values sorted by their respective keys order.
+    pub async fn values(&self) -> Result<Vec<V>, Error> {
+        self.request(Action::Values).await
+    }
 
-        match rx_o.await {
-            Ok(Some(Types::Boolean(true))) => Ok(true),
-            Err(e) => Err(format!("get mut failed {} with error: {:?}", k, e)),
-            _ => Ok(false),
-        }
+    /// Method `range` is equivalent to [`std::collection::BTreeMap range`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.range),
+    /// It returns a vector containing the key value pairs inside `range` sorted by key, only copying the matching entries.
+    /// Any range of `K` is accepted, like `a..b`, `a..=b`, `a..` or `..b`. An empty or inverted range returns an empty vector.
+    pub async fn range<R: RangeBounds<K>>(&self, range: R) -> Result<Vec<(K, V)>, Error> {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        self.request(|tx_o| Action::Range(start, end, tx_o)).await
     }
 
-    /// Method `len` is equivalent to [`std::collection::BTreeMap len`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.len),
-    /// It returns the length of the btree as a usize.
-    pub async fn len(&self) -> Result<usize, String> {
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::Len;
-        let send = (action, tx_o);
+    /// Method `first_key_value` is equivalent to [`std::collection::BTreeMap first_key_value`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.first_key_value),
+    /// It returns the entry with the smallest key, or `Ok(None)` if the `BTree` is empty.
+    pub async fn first_key_value(&self) -> Result<Option<(K, V)>, Error> {
+        self.request(|tx_o| Action::First(Bound::Unbounded, tx_o))
+            .await
+    }
 
-        tx.send(send)
+    /// Method `last_key_value` is equivalent to [`std::collection::BTreeMap last_key_value`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.last_key_value),
+    /// It returns the entry with the largest key, or `Ok(None)` if the `BTree` is empty.
+    pub async fn last_key_value(&self) -> Result<Option<(K, V)>, Error> {
+        self.request(|tx_o| Action::Last(Bound::Unbounded, tx_o))
             .await
-            .map_err(|_| format!("receiver dropped, len",))?;
+    }
 
-        match rx_o.await {
-            Ok(Some(Types::UInteger(len))) => Ok(len),
-            _ => Err(format!("len failed")),
-        }
+    /// Method `pop_first` is equivalent to [`std::collection::BTreeMap pop_first`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.pop_first),
+    /// It removes and returns the entry with the smallest key, or `Ok(None)` if the `BTree` is empty.
+    pub async fn pop_first(&self) -> Result<Option<(K, V)>, Error> {
+        self.request(Action::PopFirst).await
     }
 
-    /// Method `keys` is equivalent to [`std::collection::BTreeMap keys`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.keys),
-    /// It returns a vector containing all the keys sorted.
-    /// For `BTree` the keys are always `String`.
-    pub async fn keys(&self) -> Result<Vec<String>, String> {
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::Keys;
-        let send = (action, tx_o);
+    /// Method `pop_last` is equivalent to [`std::collection::BTreeMap pop_last`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.pop_last),
+    /// It removes and returns the entry with the largest key, or `Ok(None)` if the `BTree` is empty.
+    pub async fn pop_last(&self) -> Result<Option<(K, V)>, Error> {
+        self.request(Action::PopLast).await
+    }
 
-        tx.send(send)
+    /// Method `next_after` returns the entry with the smallest key strictly greater than `k`, or `Ok(None)` if there is none.
+    /// `k` does not need to exist in the `BTree`.
+    pub async fn next_after(&self, k: K) -> Result<Option<(K, V)>, Error> {
+        self.request(|tx_o| Action::First(Bound::Excluded(k), tx_o))
             .await
-            .map_err(|_| format!("receiver dropped, get keys"))?;
-
-        match rx_o.await {
-            Ok(Some(Types::Vector(types))) => {
-                let vec = types
-                    .into_iter()
-                    .map(|k| k.try_into())
-                    .collect::<Result<Vec<String>, String>>();
-                vec
-            }
-            Err(e) => Err(format!("get keys failed with error: {:?}", e)),
-            _ => Err(format!("get keys failed")),
-        }
     }
 
-    /// Method `values` is equivalent to [`std::collection::BTreeMap values`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.values),
-    /// It returns a vector containing all the values sorted by their respective keys order.
-    pub async fn values(&self) -> Result<Vec<Types>, String> {
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::Values;
-        let send = (action, tx_o);
-
-        tx.send(send)
+    /// Method `prev_before` returns the entry with the largest key strictly smaller than `k`, or `Ok(None)` if there is none.
+    /// `k` does not need to exist in the `BTree`.
+    pub async fn prev_before(&self, k: K) -> Result<Option<(K, V)>, Error> {
+        self.request(|tx_o| Action::Last(Bound::Excluded(k), tx_o))
             .await
-            .map_err(|_| format!("receiver dropped, get values"))?;
+    }
 
-        match rx_o.await {
-            Ok(Some(Types::Vector(types))) => Ok(types),
-            Err(e) => Err(format!("get values failed with error: {:?}", e)),
-            _ => Err(format!("get values failed")),
-        }
+    /// Method `iter` returns an `Iter` over every entry sorted by key, fetching `page_size` entries at a time.
+    /// `Iter` is a `Stream` of `Result<(K, V), Error>` and its pages can also be read with `next_page`.
+    pub fn iter(&self, page_size: usize) -> Iter<K, V> {
+        Iter::new(self.clone(), Bound::Unbounded, page_size)
+    }
+
+    /// Method `iter_from` returns an `Iter` over the entries with a key greater than or equal to `k`,
+    /// fetching `page_size` entries at a time.
+    pub fn iter_from(&self, k: K, page_size: usize) -> Iter<K, V> {
+        Iter::new(self.clone(), Bound::Included(k), page_size)
     }
 
     /// Method `remove` is equivalent to [`std::collection::BTreeMap remove`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.remove),
     /// It returns the value removed from the `BTree` for the key passed as argument. If no key is found the return is `Ok(None)`,
-    /// else it returns `Ok(Some(Types::_))`.
-    pub async fn remove(&self, k: String) -> Result<Option<Types>, String> {
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::Remove(k.clone());
-        let send = (action, tx_o);
+    /// else it returns `Ok(Some(V))`.
+    pub async fn remove(&self, k: K) -> Result<Option<V>, Error> {
+        self.request(|tx_o| Action::Remove(k, tx_o)).await
+    }
 
-        tx.send(send)
+    /// Method `remove_entry` is equivalent to [`std::collection::BTreeMap remove_entry`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.remove_entry),
+    /// It returns the key and value removed from the `BTree` for the key passed as argument as a `Option<(K, V)>`.
+    /// If no key is found the return is `Ok(None)`,
+    pub async fn remove_entry(&self, k: K) -> Result<Option<(K, V)>, Error> {
+        self.request(|tx_o| Action::RemoveEntry(k, tx_o)).await
+    }
+
+    /// Method `entry` is equivalent to [`std::collection::BTreeMap entry`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.entry),
+    /// It returns an `Entry` for key `k`, like `btree.entry(k).and_modify(|v| *v += 1).or_insert(1).await`.
+    pub fn entry(&self, k: K) -> Entry<'_, K, V> {
+        Entry::new(self, k)
+    }
+
+    /// Method `update` runs `f` inside the `BTree` thread with the value of `k`, or `None` if the key does not exist,
+    /// and returns its result. No other action is processed while `f` runs, so any read-modify-write is atomic.
+    /// Observers receive an `Event::Change` if `f` modified the value, the value is only copied and compared when the key is watched.
+    /// A value containing a `NaN` is never equal to itself, so it is always reported as changed.
+    /// `f` should be quick. If it panics, `Err(Error::Panicked)` is returned and the `BTree` keeps running,
+    /// with any change `f` made before panicking.
+    pub async fn update<F, R>(&self, k: K, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(Option<&mut V>) -> R + Send + 'static,
+        R: Send + 'static,
+        V: PartialEq,
+    {
+        self.request(|tx_o| {
+            Action::Update(
+                k,
+                Box::new(move |x, old| {
+                    let (result, modified) = match x {
+                        Some(x) => {
+                            let result = catch_panic(|| f(Some(&mut *x)));
+                            (result, old.is_some_and(|old| old != x))
+                        }
+                        None => (catch_panic(|| f(None)), false),
+                    };
+                    if tx_o.send(result).is_err() {
+                        println!("the receiver dropped, mpsc update");
+                    }
+                    modified
+                }),
+            )
+        })
+        .await?
+    }
+
+    /// Method `compare_and_swap` replaces the value at key `k` with `new` only if the current value is equal to `expected`,
+    /// where `None` means the key does not exist. A `new` of `None` removes the key.
+    /// The comparison and the swap are executed as a single action, so no other task can change the key in between.
+    /// It returns whether the swap happened and the value at the key after the operation.
+    pub async fn compare_and_swap(
+        &self,
+        k: K,
+        expected: Option<V>,
+        new: Option<V>,
+    ) -> Result<(bool, Option<V>), Error>
+    where
+        V: PartialEq,
+    {
+        let expected: Check<V> = Box::new(move |current| current == expected.as_ref());
+        self.request(|tx_o| Action::CompareAndSwap(k, expected, new, tx_o))
             .await
-            .map_err(|_| format!("receiver dropped, remove key {}", k))?;
+    }
 
-        match rx_o.await {
-            Ok(types) => Ok(types),
-            Err(e) => Err(format!("remove failed {} with error: {:?}", k, e)),
-        }
+    /// Method `apply` applies every operation of a `Batch` atomically, no other action is processed in between.
+    /// It returns `Ok(())` if all operations succeeded. If any operation fails every change is rolled back and its error is returned,
+    /// like `Err(Error::NotFound)` for a missing key or `Err(Error::Overflow)`. Subscriptions receive the changes as a single `Event::Batch`.
+    pub async fn apply(&self, batch: Batch<K, V>) -> Result<(), Error> {
+        self.request(|tx_o| Action::Batch(batch, tx_o)).await?
     }
 
-    /// Method `remove_entry` is equivalent to [`std::collection::BTreeMap remove_entry`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.remove_entry),
-    /// It returns the key and value removed from the `BTree` for the key passed as argument as a `Option<Types::KeyValue(_,_)>`.
-    /// If no key is found the return is `Ok(None)`,
-    pub async fn remove_entry(&self, k: String) -> Result<Option<Types>, String> {
-        let tx = self.tx.clone();
-        let (tx_o, rx_o) = oneshot::channel();
-        let action = Action::RemoveEntry(k.clone());
-        let send = (action, tx_o);
+    /// Method `clear` is equivalent to [`std::collection::BTreeMap clear`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.clear),
+    /// It removes every entry in a single action, observers receive the removals as one `Event::Batch`.
+    pub async fn clear(&self) -> Result<(), Error> {
+        self.request(Action::Clear).await
+    }
+
+    /// Method `extend` is equivalent to [`std::collection::BTreeMap extend`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.extend),
+    /// It inserts every key value pair of `iter` in a single action, observers receive the insertions as one `Event::Batch`.
+    pub async fn extend<T, I>(&self, iter: I) -> Result<(), Error>
+    where
+        T: Into<V>,
+        I: IntoIterator<Item = (K, T)>,
+    {
+        let entries: Vec<(K, V)> = iter.into_iter().map(|(k, v)| (k, v.into())).collect();
+        self.request(|tx_o| Action::Extend(entries, tx_o)).await
+    }
+
+    /// Method `retain` is equivalent to [`std::collection::BTreeMap retain`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.retain),
+    /// It removes every entry for which `f` returns false in a single action, observers receive the removals as one `Event::Batch`.
+    /// `f` is called inside the `BTree` thread and, unlike `BTreeMap::retain`, it can't modify the values.
+    /// If `f` panics, `Err(Error::Panicked)` is returned, the entries removed before the panic stay removed
+    /// and the remaining ones are kept.
+    pub async fn retain<F>(&self, f: F) -> Result<(), Error>
+    where
+        F: FnMut(&K, &V) -> bool + Send + 'static,
+    {
+        let f: Retain<K, V> = Box::new(f);
+        self.request(|tx_o| Action::Retain(f, tx_o)).await?
+    }
+
+    /// Method `drain_range` removes every entry inside `range` in a single action and returns them sorted by key.
+    /// Observers receive the removals as one `Event::Batch`. An empty or inverted range removes nothing.
+    pub async fn drain_range<R: RangeBounds<K>>(&self, range: R) -> Result<Vec<(K, V)>, Error> {
+        let start = range.start_bound().cloned();
+        let end = range.end_bound().cloned();
+        self.request(|tx_o| Action::DrainRange(start, end, tx_o))
+            .await
+    }
+
+    /// Method `watch` subscribes to the changes applied to a key.
+    /// The returned `Subscription` yields an `Event::Change`, containing the old value, the new value and the `ChangeKind`,
+    /// every time a method like `insert`, `get_mut`, `compare_and_swap` or `remove` changes the key. Changes applied before `watch` returns are not observed.
+    /// It uses the default `Backpressure` policy, use `subscribe` to choose another one.
+    pub async fn watch(&self, k: K) -> Result<Subscription<K, V>, Error> {
+        self.subscribe(KeyFilter::Key(k), Backpressure::default())
+            .await
+    }
 
-        tx.send(send)
+    /// Method `watch_range` subscribes to the changes applied to any key inside `range`, like `"a".to_string().."n".to_string()`.
+    /// Keys are compared with the same ordering used by the `BTree`.
+    pub async fn watch_range<R: RangeBounds<K>>(
+        &self,
+        range: R,
+    ) -> Result<Subscription<K, V>, Error> {
+        self.subscribe(KeyFilter::range(range), Backpressure::default())
             .await
-            .map_err(|_| format!("receiver dropped, remove_entry key {}", k))?;
+    }
 
-        match rx_o.await {
-            Ok(types) => Ok(types),
-            Err(e) => Err(format!("remove_entry failed {} with error: {:?}", k, e)),
+    /// Method `changes` subscribes to every change applied to the `BTree`.
+    /// The returned `Subscription` is a `futures::Stream` of `Event`s in the exact order the mutations were applied.
+    pub async fn changes(&self) -> Result<Subscription<K, V>, Error> {
+        self.subscribe(KeyFilter::All, Backpressure::default())
+            .await
+    }
+
+    /// Method `subscribe` subscribes to the changes applied to the keys selected by `filter`,
+    /// using `backpressure` as the policy for when the `Subscription` is not consumed fast enough.
+    /// When events are discarded, the `Subscription` yields an `Event::Lagged` with how many were missed.
+    pub async fn subscribe(
+        &self,
+        filter: KeyFilter<K>,
+        backpressure: Backpressure,
+    ) -> Result<Subscription<K, V>, Error> {
+        let (observer, subscription) = Subscription::new(filter, backpressure);
+        self.request(|tx_o| Action::Watch(observer, tx_o)).await?;
+
+        Ok(subscription)
+    }
+
+    /// Method `wait_for` waits until the value stored at key `k` satisfies `predicate`, returning that value.
+    /// It resolves immediately if the current value already satisfies it, and every later change is checked in order,
+    /// so a task can wait for another task's write without polling `get`. Use `|_| true` to wait for the key to exist.
+    /// If `timeout` is `Some` and it elapses before the predicate is satisfied `Err(Error::Timeout)` is returned.
+    pub async fn wait_for<F>(
+        &self,
+        k: K,
+        predicate: F,
+        timeout: Option<Duration>,
+    ) -> Result<V, Error>
+    where
+        F: Fn(&V) -> bool,
+    {
+        let wait = async {
+            let mut watch = self.watch(k.clone()).await?;
+            let mut current = self.get(k.clone()).await?;
+            loop {
+                if let Some(value) = current.filter(|v| predicate(v)) {
+                    return Ok(value);
+                }
+                current = match watch.recv().await {
+                    Some(Event::Change(change)) => change.new,
+                    // a batch is atomic, so only the last change to the key was ever visible
+                    Some(Event::Batch(mut changes)) => changes.pop().and_then(|change| change.new),
+                    Some(Event::Lagged(_)) => self.get(k.clone()).await?,
+                    Some(Event::Shutdown) | None => return Err(Error::ActorStopped),
+                };
+            }
+        };
+
+        match timeout {
+            Some(duration) => tokio::time::timeout(duration, wait)
+                .await
+                .map_err(|_| Error::Timeout)?,
+            None => wait.await,
         }
     }
+
+    /// Method `shutdown` stops the `BTree` thread.
+    /// Actions already queued are still applied, new ones fail with `Error::ActorStopped`,
+    /// and every `Subscription` receives a final `Event::Shutdown`.
+    /// The returned future completes when the thread has exited.
+    pub async fn shutdown(&self) {
+        let _ = self.tx.send(Action::Shutdown(None)).await;
+
+        let mut exit = self.exit.clone();
+        while exit.changed().await.is_ok() {}
+    }
+
+    /// Method `snapshot` returns a copy of the whole `BTreeMap`, taken in a single action.
+    pub async fn snapshot(&self) -> Result<BTreeMap<K, V>, Error> {
+        self.request(Action::Snapshot).await
+    }
+
+    /// Method `into_inner` stops the `BTree` thread like `shutdown` and returns the `BTreeMap` once every queued action was applied.
+    /// Other handles can't use the `BTree` after it. If the thread was already stopping, it fails with `Error::ActorStopped`
+    /// or `Error::ReplyDropped` when another `into_inner` was queued before it.
+    pub async fn into_inner(self) -> Result<BTreeMap<K, V>, Error> {
+        self.request(|tx_o| Action::Shutdown(Some(tx_o))).await
+    }
+}
+
+impl<K: Key, V: Value> From<BTreeMap<K, V>> for BTree<K, V> {
+    /// Starts a `BTree` containing the entries of `map` with a buffer of `DEFAULT_BUFFER_SIZE` actions.
+    /// Like `BTree::spawn`, it must be called inside a tokio runtime.
+    fn from(map: BTreeMap<K, V>) -> Self {
+        Self::spawn_with(map, DEFAULT_BUFFER_SIZE)
+    }
+}
+
+impl<V: Value> BTree<String, V> {
+    /// Method `watch_prefix` subscribes to the changes applied to any key starting with `prefix`, like `"user:"`.
+    pub async fn watch_prefix(&self, prefix: &str) -> Result<Subscription<String, V>, Error> {
+        self.subscribe(KeyFilter::prefix(prefix), Backpressure::default())
+            .await
+    }
+
+    /// Method `scan_prefix` returns the key value pairs whose key starts with `prefix`, like `"tenant:123:"`, sorted by key.
+    /// It is a `range` over the keys starting with `prefix`, so only the matching entries are visited.
+    pub async fn scan_prefix(&self, prefix: &str) -> Result<Vec<(String, V)>, Error> {
+        let (start, end) = prefix_range(prefix);
+        self.request(|tx_o| Action::Range(start, end, tx_o)).await
+    }
+}
+
+impl<K: Key> BTree<K, Types> {
+    /// `BTree::spawn_with_operations(map, operations, buffer_size)` is the same as `BTree::spawn_with`,
+    /// but `Operation::Custom` can apply the `CustomOperation`s of `operations`.
+    pub fn spawn_with_operations(
+        map: BTreeMap<K, Types>,
+        operations: Registry,
+        buffer_size: usize,
+    ) -> Self {
+        Self::spawn_with_registry(map, operations, buffer_size)
+    }
+
+    /// Method `get_mut` is equivalent to [`std::collection::BTreeMap get_mut`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html#method.get_mut),
+    /// It applies an `Operation` to the value obtained and returns true if the operation succeeded or false if it failed.
+    pub async fn get_mut<T: Into<Types>>(&self, k: K, v: T, op: Operation) -> Result<bool, Error> {
+        let v: Types = v.into();
+        let operations = self.operations.clone();
+        let f: Modify<Types> = Box::new(move |x| try_apply_with(x, v, op, &operations).is_ok());
+
+        self.request(|tx_o| Action::GetMut(k, f, tx_o)).await
+    }
+
+    /// Method `get_mut_values` applies an `Operation` to the value of `k` like `get_mut`,
+    /// returning `Ok(Some((previous, updated)))` with the values before and after the operation.
+    /// It returns `Ok(None)` if the key does not exist and `Err(Error::Unsupported)` if `op` can't be applied to the value.
+    pub async fn get_mut_values<T: Into<Types>>(
+        &self,
+        k: K,
+        v: T,
+        op: Operation,
+    ) -> Result<Option<(Types, Types)>, Error> {
+        let v: Types = v.into();
+        let operations = self.operations.clone();
+        let f: TryModify<Types> = Box::new(move |x| try_apply_with(x, v, op, &operations));
+
+        self.request(|tx_o| Action::GetMutValues(k, f, tx_o))
+            .await?
+            .transpose()
+    }
+
+    /// Method `get_path` returns a copy of the element at `path` inside the value of `k`, like `"/config/limits/3"`,
+    /// without copying the rest of the value. It returns `Ok(None)` if the key or the path does not exist.
+    pub async fn get_path<P: Into<Path>>(&self, k: K, path: P) -> Result<Option<Types>, Error> {
+        let path: Path = path.into();
+        self.request(|tx_o| {
+            Action::Read(
+                k,
+                Box::new(move |x| {
+                    let get = x.and_then(|x| path::get(x, &path)).cloned();
+                    if tx_o.send(get).is_err() {
+                        println!("the receiver dropped, mpsc get path");
+                    }
+                }),
+            )
+        })
+        .await
+    }
+
+    /// Method `get_mut_custom` applies the `CustomOperation` registered with `name` with `arg` to the value of `k`
+    /// and returns the result of the operation. It returns `Ok(None)` if the key does not exist,
+    /// `Err(Error::UnknownOperation)` if no operation is registered with `name` and the error of the operation if it fails.
+    /// The value is not modified when it fails.
+    pub async fn get_mut_custom<T: Into<Types>>(
+        &self,
+        k: K,
+        name: &str,
+        arg: T,
+    ) -> Result<Option<Types>, Error> {
+        let name = name.to_owned();
+        let arg: Types = arg.into();
+        let operations = self.operations.clone();
+        self.request(|tx_o| {
+            Action::Update(
+                k,
+                Box::new(move |x, _| {
+                    let result = x.map(|x| try_apply_custom(x, arg, &name, &operations));
+                    let modified = matches!(result, Some(Ok(_)));
+                    if tx_o.send(result).is_err() {
+                        println!("the receiver dropped, mpsc get mut custom");
+                    }
+                    modified
+                }),
+            )
+        })
+        .await?
+        .transpose()
+    }
+
+    /// Method `get_mut_path` applies an `Operation` with `v` to the element at `path` inside the value of `k`
+    /// and returns the updated element. `Operation::Replace` also inserts a missing last key in a map.
+    /// It returns `Ok(None)` if the key does not exist, `Err(Error::NotFound)` or `Err(Error::IndexOutOfBounds)` if the path does not exist
+    /// and the errors of `get_mut_values` if the operation can't be applied. The value is not modified when it fails.
+    pub async fn get_mut_path<P: Into<Path>, T: Into<Types>>(
+        &self,
+        k: K,
+        path: P,
+        v: T,
+        op: Operation,
+    ) -> Result<Option<Types>, Error> {
+        let path: Path = path.into();
+        let v: Types = v.into();
+        let operations = self.operations.clone();
+        self.request(|tx_o| {
+            Action::Update(
+                k,
+                Box::new(move |x, _| {
+                    let update = x.map(|x| path::apply(x, &path, v, op, &operations));
+                    let modified = matches!(update, Some(Ok(_)));
+                    if tx_o.send(update).is_err() {
+                        println!("the receiver dropped, mpsc get mut path");
+                    }
+                    modified
+                }),
+            )
+        })
+        .await?
+        .transpose()
+    }
+
+    /// Method `remove_path` removes the element at `path` inside the value of `k` and returns it.
+    /// It returns `Ok(None)` if the key does not exist and `Err(Error::NotFound)` or `Err(Error::IndexOutOfBounds)`
+    /// if the path does not exist or is empty.
+    pub async fn remove_path<P: Into<Path>>(&self, k: K, path: P) -> Result<Option<Types>, Error> {
+        let path: Path = path.into();
+        self.request(|tx_o| {
+            Action::Update(
+                k,
+                Box::new(move |x, _| {
+                    let remove = x.map(|x| path::remove(x, &path));
+                    let modified = matches!(remove, Some(Ok(_)));
+                    if tx_o.send(remove).is_err() {
+                        println!("the receiver dropped, mpsc remove path");
+                    }
+                    modified
+                }),
+            )
+        })
+        .await?
+        .transpose()
+    }
+
+    /// Method `get_mut_old` is the same as `get_mut_values`, but only returns the value before the operation.
+    pub async fn get_mut_old<T: Into<Types>>(
+        &self,
+        k: K,
+        v: T,
+        op: Operation,
+    ) -> Result<Option<Types>, Error> {
+        let values = self.get_mut_values(k, v, op).await?;
+        Ok(values.map(|(old, _)| old))
+    }
+
+    /// Method `get_mut_new` is the same as `get_mut_values`, but only returns the value after the operation,
+    /// like the new value of a counter after `Operation::Add`.
+    pub async fn get_mut_new<T: Into<Types>>(
+        &self,
+        k: K,
+        v: T,
+        op: Operation,
+    ) -> Result<Option<Types>, Error> {
+        let values = self.get_mut_values(k, v, op).await?;
+        Ok(values.map(|(_, new)| new))
+    }
 }
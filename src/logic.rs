@@ -1,45 +1,432 @@
-use crate::model::Types;
+use std::{cmp::Ordering, collections::HashMap, sync::Arc};
 
-pub fn add(x: &mut Types, v: Types) -> Option<Types> {
-    match (x.clone(), v) {
-        (Types::Integer(xx), Types::Integer(vv)) => {
-            *x = Types::Integer(xx + vv);
-            Some(Types::Boolean(true))
-        }
-        (Types::UInteger(xx), Types::UInteger(vv)) => {
-            *x = Types::UInteger(xx + vv);
-            Some(Types::Boolean(true))
+use crate::model::{Operation, Types};
+use crate::{catch_panic, Error};
+
+/// `CustomOperation` is a named operation registered in a `Registry` and applied with `Operation::Custom(name)`.
+/// It is implemented for every `Fn(&mut Types, Types) -> Result<Types, Error>`.
+pub trait CustomOperation: Send + Sync {
+    /// Applies the operation with `arg` to `current`, returning the result of the operation,
+    /// which is returned to the caller by `BTree::get_mut_custom`.
+    /// A failure specific to the operation can be returned as `Error::Custom` with its reason.
+    /// If it fails or panics, `current` is restored to its value before the operation, a panic is returned as `Error::Panicked`.
+    fn apply(&self, current: &mut Types, arg: Types) -> Result<Types, Error>;
+}
+
+impl<F> CustomOperation for F
+where
+    F: Fn(&mut Types, Types) -> Result<Types, Error> + Send + Sync,
+{
+    fn apply(&self, current: &mut Types, arg: Types) -> Result<Types, Error> {
+        self(current, arg)
+    }
+}
+
+/// `Registry` holds the `CustomOperation`s available to a `BTree`, it is passed to the `BTree` when it is started,
+/// like `BTree::start_with_operations(Registry::new().register("append_line", append_line), 1000)`.
+#[derive(Clone, Default)]
+pub struct Registry {
+    operations: HashMap<String, Arc<dyn CustomOperation>>,
+}
+
+impl Registry {
+    /// Creates an empty `Registry`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `operation` with `name`, replacing any operation previously registered with the same name.
+    pub fn register<T, O>(mut self, name: T, operation: O) -> Self
+    where
+        T: Into<String>,
+        O: CustomOperation + 'static,
+    {
+        self.operations.insert(name.into(), Arc::new(operation));
+        self
+    }
+
+    /// Returns the operation registered with `name`.
+    pub fn get(&self, name: &str) -> Option<&dyn CustomOperation> {
+        self.operations
+            .get(name)
+            .map(|operation| operation.as_ref())
+    }
+}
+
+/// Applies `op` with `v` to `x`, returning `true` if `x` was modified.
+pub fn apply(x: &mut Types, v: Types, op: Operation) -> bool {
+    try_apply(x, v, op).is_ok()
+}
+
+/// Applies `op` with `v` to `x`, failing with `Error::Unsupported` if `op` can't be applied to the type of `x`,
+/// `Error::Overflow` if an integer operation overflows and `Error::DivisionByZero` if an integer is divided by zero.
+/// `x` is not modified when it fails. `Operation::Custom` fails with `Error::UnknownOperation`, use `try_apply_with` instead.
+pub fn try_apply(x: &mut Types, v: Types, op: Operation) -> Result<(), Error> {
+    try_apply_with(x, v, op, &Registry::default())
+}
+
+/// Same as `try_apply`, but `Operation::Custom` is looked up in `operations`.
+pub fn try_apply_with(
+    x: &mut Types,
+    v: Types,
+    op: Operation,
+    operations: &Registry,
+) -> Result<(), Error> {
+    let name = op.name();
+    match op {
+        Operation::Custom(custom) => try_apply_custom(x, v, &custom, operations).map(|_| ()),
+        Operation::Replace => {
+            *x = v;
+            Ok(())
         }
-        (Types::Float(xx), Types::Float(vv)) => {
-            *x = Types::Float(xx + vv);
-            Some(Types::Boolean(true))
+        Operation::Add => try_add(x, v),
+        Operation::Subtract => numeric(
+            x,
+            v,
+            name,
+            isize::checked_sub,
+            usize::checked_sub,
+            |xx, vv| xx - vv,
+        ),
+        Operation::Multiply => numeric(
+            x,
+            v,
+            name,
+            isize::checked_mul,
+            usize::checked_mul,
+            |xx, vv| xx * vv,
+        ),
+        Operation::Divide => numeric(
+            x,
+            v,
+            name,
+            isize::checked_div,
+            usize::checked_div,
+            |xx, vv| xx / vv,
+        ),
+        Operation::Modulo => numeric(
+            x,
+            v,
+            name,
+            isize::checked_rem,
+            usize::checked_rem,
+            |xx, vv| xx % vv,
+        ),
+        Operation::Min => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.min(vv)),
+            |xx, vv| Some(xx.min(vv)),
+            f64::min,
+        ),
+        Operation::Max => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.max(vv)),
+            |xx, vv| Some(xx.max(vv)),
+            f64::max,
+        ),
+        Operation::Negate => negate(x),
+        Operation::SaturatingAdd => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.saturating_add(vv)),
+            |xx, vv| Some(xx.saturating_add(vv)),
+            |xx, vv| xx + vv,
+        ),
+        Operation::SaturatingSubtract => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.saturating_sub(vv)),
+            |xx, vv| Some(xx.saturating_sub(vv)),
+            |xx, vv| xx - vv,
+        ),
+        Operation::SaturatingMultiply => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.saturating_mul(vv)),
+            |xx, vv| Some(xx.saturating_mul(vv)),
+            |xx, vv| xx * vv,
+        ),
+        Operation::WrappingAdd => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.wrapping_add(vv)),
+            |xx, vv| Some(xx.wrapping_add(vv)),
+            |xx, vv| xx + vv,
+        ),
+        Operation::WrappingSubtract => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.wrapping_sub(vv)),
+            |xx, vv| Some(xx.wrapping_sub(vv)),
+            |xx, vv| xx - vv,
+        ),
+        Operation::WrappingMultiply => numeric(
+            x,
+            v,
+            name,
+            |xx, vv| Some(xx.wrapping_mul(vv)),
+            |xx, vv| Some(xx.wrapping_mul(vv)),
+            |xx, vv| xx * vv,
+        ),
+        Operation::RemoveIndex
+        | Operation::RemoveValue
+        | Operation::PopFront
+        | Operation::PopBack
+        | Operation::Truncate
+        | Operation::Dedup
+        | Operation::Sort => vector(x, v, op),
+        Operation::RemoveKey => remove_key(x, v),
+    }
+}
+
+/// Applies the `CustomOperation` registered with `name` with `arg` to `x`, returning the result of the operation.
+/// It fails with `Error::UnknownOperation` if no operation is registered with `name`, `x` is not modified when it fails.
+pub fn try_apply_custom(
+    x: &mut Types,
+    arg: Types,
+    name: &str,
+    operations: &Registry,
+) -> Result<Types, Error> {
+    let operation = operations
+        .get(name)
+        .ok_or_else(|| Error::UnknownOperation(name.to_owned()))?;
+    let old = x.clone();
+    match catch_panic(|| operation.apply(x, arg)) {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(e)) | Err(e) => {
+            *x = old;
+            Err(e)
         }
+    }
+}
+
+/// Adds `v` to `x`, returning `None` if the types can't be added or if an integer addition overflows.
+pub fn add(x: &mut Types, v: Types) -> Option<Types> {
+    try_add(x, v).ok().map(|_| Types::Boolean(true))
+}
+
+fn try_add(x: &mut Types, v: Types) -> Result<(), Error> {
+    if let (Types::Integer(_), Types::Integer(_))
+    | (Types::UInteger(_), Types::UInteger(_))
+    | (Types::Float(_), Types::Float(_)) = (&*x, &v)
+    {
+        return numeric(
+            x,
+            v,
+            "Add",
+            isize::checked_add,
+            usize::checked_add,
+            |xx, vv| xx + vv,
+        );
+    }
+
+    let unsupported = Error::Unsupported {
+        operation: "Add",
+        actual: x.type_name(),
+    };
+    match (x.clone(), v) {
         (Types::String(xx), Types::String(vv)) => {
             *x = Types::String(xx + &vv);
-            Some(Types::Boolean(true))
+            Ok(())
         }
         (Types::Vector(mut xx), Types::Vector(mut vv)) => {
             xx.append(&mut vv);
             *x = Types::Vector(xx);
-            Some(Types::Boolean(true))
+            Ok(())
         }
         (Types::HashMap(mut xx), Types::KeyValue(k, vv)) => {
             let new_v = vv.as_ref();
             xx.insert(k, new_v.to_owned());
             *x = Types::HashMap(xx);
-            Some(Types::Boolean(true))
+            Ok(())
         }
         (Types::BTreeMap(mut xx), Types::KeyValue(k, vv)) => {
             let new_v = vv.as_ref();
             xx.insert(k, new_v.to_owned());
             *x = Types::BTreeMap(xx);
-            Some(Types::Boolean(true))
+            Ok(())
         }
         (Types::Vector(mut xx), vv) => {
             xx.push(vv);
             *x = Types::Vector(xx);
-            Some(Types::Boolean(true))
+            Ok(())
+        }
+        _ => Err(unsupported),
+    }
+}
+
+/// Applies a numeric operation to `x` and `v` of the same type, `Integer`, `UInteger` or `Float`.
+/// Integer operations returning `None` are reported as `Error::Overflow`.
+fn numeric(
+    x: &mut Types,
+    v: Types,
+    operation: &'static str,
+    int: fn(isize, isize) -> Option<isize>,
+    uint: fn(usize, usize) -> Option<usize>,
+    float: fn(f64, f64) -> f64,
+) -> Result<(), Error> {
+    // a checked operation by 0 can only fail when it is a division or a modulo.
+    let failed = |by_zero: bool| {
+        if by_zero {
+            Error::DivisionByZero
+        } else {
+            Error::Overflow { operation }
+        }
+    };
+    let result = match (&*x, v) {
+        (Types::Integer(xx), Types::Integer(vv)) => {
+            Types::Integer(int(*xx, vv).ok_or_else(|| failed(vv == 0))?)
+        }
+        (Types::UInteger(xx), Types::UInteger(vv)) => {
+            Types::UInteger(uint(*xx, vv).ok_or_else(|| failed(vv == 0))?)
+        }
+        (Types::Float(xx), Types::Float(vv)) => Types::Float(float(*xx, vv)),
+        _ => {
+            return Err(Error::Unsupported {
+                operation,
+                actual: x.type_name(),
+            })
+        }
+    };
+    *x = result;
+    Ok(())
+}
+
+fn negate(x: &mut Types) -> Result<(), Error> {
+    let result = match &*x {
+        Types::Integer(xx) => Types::Integer(xx.checked_neg().ok_or(Error::Overflow {
+            operation: "Negate",
+        })?),
+        Types::Float(xx) => Types::Float(-xx),
+        _ => {
+            return Err(Error::Unsupported {
+                operation: "Negate",
+                actual: x.type_name(),
+            })
+        }
+    };
+    *x = result;
+    Ok(())
+}
+
+/// Applies a collection operation to a `Types::Vector`.
+fn vector(x: &mut Types, v: Types, op: Operation) -> Result<(), Error> {
+    let xx = match x {
+        Types::Vector(xx) => xx,
+        _ => {
+            return Err(Error::Unsupported {
+                operation: op.name(),
+                actual: x.type_name(),
+            })
         }
+    };
+    match op {
+        Operation::RemoveIndex => {
+            let index = index(&v)?;
+            if index >= xx.len() {
+                return Err(Error::IndexOutOfBounds {
+                    index,
+                    len: xx.len(),
+                });
+            }
+            xx.remove(index);
+        }
+        Operation::RemoveValue => {
+            let index = xx.iter().position(|e| e == &v).ok_or(Error::NotFound)?;
+            xx.remove(index);
+        }
+        Operation::PopFront => {
+            if xx.is_empty() {
+                return Err(Error::NotFound);
+            }
+            xx.remove(0);
+        }
+        Operation::PopBack => {
+            xx.pop().ok_or(Error::NotFound)?;
+        }
+        Operation::Truncate => xx.truncate(index(&v)?),
+        Operation::Dedup => {
+            let mut unique: Vec<Types> = Vec::with_capacity(xx.len());
+            for e in xx.drain(..) {
+                if !unique.contains(&e) {
+                    unique.push(e);
+                }
+            }
+            *xx = unique;
+        }
+        Operation::Sort => {
+            let sortable = xx.iter().all(|e| compare(e, &xx[0]).is_some());
+            if !sortable {
+                return Err(Error::Unsupported {
+                    operation: op.name(),
+                    actual: "Vector",
+                });
+            }
+            xx.sort_by(|a, b| compare(a, b).unwrap_or(Ordering::Equal));
+        }
+        _ => {
+            return Err(Error::Unsupported {
+                operation: op.name(),
+                actual: "Vector",
+            })
+        }
+    }
+    Ok(())
+}
+
+fn remove_key(x: &mut Types, v: Types) -> Result<(), Error> {
+    let k = match v {
+        Types::String(k) => k,
+        v => {
+            return Err(Error::TypeMismatch {
+                expected: "String",
+                actual: v.type_name(),
+            })
+        }
+    };
+    let removed = match x {
+        Types::HashMap(xx) => xx.remove(&k),
+        Types::BTreeMap(xx) => xx.remove(&k),
+        _ => {
+            return Err(Error::Unsupported {
+                operation: "RemoveKey",
+                actual: x.type_name(),
+            })
+        }
+    };
+    removed.map(|_| ()).ok_or(Error::NotFound)
+}
+
+/// Reads an index or a length passed as `UInteger`, or as a non negative `Integer`.
+fn index(v: &Types) -> Result<usize, Error> {
+    match v {
+        Types::UInteger(i) => Ok(*i),
+        Types::Integer(i) if *i >= 0 => Ok(*i as usize),
+        v => Err(Error::TypeMismatch {
+            expected: "UInteger",
+            actual: v.type_name(),
+        }),
+    }
+}
+
+/// Compares two values of the same sortable type, returning `None` for any other pair.
+fn compare(a: &Types, b: &Types) -> Option<Ordering> {
+    match (a, b) {
+        (Types::Integer(a), Types::Integer(b)) => Some(a.cmp(b)),
+        (Types::UInteger(a), Types::UInteger(b)) => Some(a.cmp(b)),
+        (Types::Float(a), Types::Float(b)) => Some(a.total_cmp(b)),
+        (Types::String(a), Types::String(b)) => Some(a.cmp(b)),
+        (Types::Char(a), Types::Char(b)) => Some(a.cmp(b)),
+        (Types::Boolean(a), Types::Boolean(b)) => Some(a.cmp(b)),
         _ => None,
     }
 }
@@ -3,10 +3,97 @@ use std::{
     convert::{TryFrom, TryInto},
 };
 
+use crate::Error;
+
+/// Bounds required for the keys of a `BTree`, it is implemented for every type satisfying them.
+pub trait Key: Ord + Clone + Send + Sync + 'static {}
+
+impl<T: Ord + Clone + Send + Sync + 'static> Key for T {}
+
+/// Bounds required for the values of a `BTree`, it is implemented for every type satisfying them.
+pub trait Value: Clone + Send + Sync + 'static {}
+
+impl<T: Clone + Send + Sync + 'static> Value for T {}
+
+/// Operations applied by `get_mut` to a `Types` value.
+/// `Add`, `Subtract`, `Multiply`, `Divide`, `Modulo` and `Negate` are checked, an integer overflow fails with `Error::Overflow`
+/// and leaves the value unchanged. The `Saturating` and `Wrapping` variants never overflow.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Operation {
     Add,
     Replace,
-    // ...
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Min,
+    Max,
+    /// Negates the value, the value passed with the operation is ignored.
+    Negate,
+    /// Integer addition clamped to the bounds of the type instead of overflowing.
+    SaturatingAdd,
+    SaturatingSubtract,
+    SaturatingMultiply,
+    /// Integer addition wrapping around the bounds of the type instead of overflowing.
+    WrappingAdd,
+    WrappingSubtract,
+    WrappingMultiply,
+    /// Removes the element at the index passed as value from a `Vector`.
+    RemoveIndex,
+    /// Removes the first element equal to the value passed from a `Vector`.
+    RemoveValue,
+    /// Removes the key passed as a `Types::String` from a `HashMap` or a `BTreeMap`.
+    RemoveKey,
+    /// Removes the first element of a `Vector`, the value passed with the operation is ignored.
+    PopFront,
+    /// Removes the last element of a `Vector`, the value passed with the operation is ignored.
+    PopBack,
+    /// Shortens a `Vector` to the length passed as value, it does nothing if the `Vector` is already shorter.
+    Truncate,
+    /// Removes the repeated elements of a `Vector`, keeping their first occurrence. The value passed is ignored.
+    Dedup,
+    /// Sorts a `Vector` whose elements are all `Integer`, `UInteger`, `Float`, `String`, `Char` or `Boolean` of the same type.
+    /// The value passed with the operation is ignored.
+    Sort,
+    /// Applies the `CustomOperation` registered with this name in the `Registry` of the `BTree`.
+    Custom(String),
+}
+
+impl Operation {
+    /// Creates an `Operation::Custom` applying the operation registered with `name`.
+    pub fn custom<T: Into<String>>(name: T) -> Self {
+        Operation::Custom(name.into())
+    }
+
+    /// Returns the name of the `Operation` variant, like `"Add"` for `Operation::Add`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Add => "Add",
+            Operation::Replace => "Replace",
+            Operation::Subtract => "Subtract",
+            Operation::Multiply => "Multiply",
+            Operation::Divide => "Divide",
+            Operation::Modulo => "Modulo",
+            Operation::Min => "Min",
+            Operation::Max => "Max",
+            Operation::Negate => "Negate",
+            Operation::SaturatingAdd => "SaturatingAdd",
+            Operation::SaturatingSubtract => "SaturatingSubtract",
+            Operation::SaturatingMultiply => "SaturatingMultiply",
+            Operation::WrappingAdd => "WrappingAdd",
+            Operation::WrappingSubtract => "WrappingSubtract",
+            Operation::WrappingMultiply => "WrappingMultiply",
+            Operation::RemoveIndex => "RemoveIndex",
+            Operation::RemoveValue => "RemoveValue",
+            Operation::RemoveKey => "RemoveKey",
+            Operation::PopFront => "PopFront",
+            Operation::PopBack => "PopBack",
+            Operation::Truncate => "Truncate",
+            Operation::Dedup => "Dedup",
+            Operation::Sort => "Sort",
+            Operation::Custom(_) => "Custom",
+        }
+    }
 }
 
 /// Available types to use as `BTree` values.
@@ -25,6 +112,32 @@ pub enum Types {
     Nil,
 }
 
+impl Types {
+    /// Returns the name of the `Types` variant, like `"Integer"` for `Types::Integer(_)`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Types::Char(_) => "Char",
+            Types::Integer(_) => "Integer",
+            Types::UInteger(_) => "UInteger",
+            Types::String(_) => "String",
+            Types::Float(_) => "Float",
+            Types::Boolean(_) => "Boolean",
+            Types::Vector(_) => "Vector",
+            Types::HashMap(_) => "HashMap",
+            Types::BTreeMap(_) => "BTreeMap",
+            Types::KeyValue(_, _) => "KeyValue",
+            Types::Nil => "Nil",
+        }
+    }
+
+    fn mismatch(&self, expected: &'static str) -> Error {
+        Error::TypeMismatch {
+            expected,
+            actual: self.type_name(),
+        }
+    }
+}
+
 impl From<char> for Types {
     fn from(t: char) -> Self {
         Types::Char(t)
@@ -124,163 +237,137 @@ impl<T: Into<Types>> From<Option<T>> for Types {
     }
 }
 
-impl TryInto<isize> for Types {
-    type Error = String;
+impl TryFrom<Types> for isize {
+    type Error = Error;
 
-    fn try_into(self) -> Result<isize, Self::Error> {
-        match self {
+    fn try_from(t: Types) -> Result<isize, Self::Error> {
+        match t {
             Types::Integer(t) => Ok(t),
-            _ => Err(format!("Could not convert {:?} to isize", self)),
+            _ => Err(t.mismatch("Integer")),
         }
     }
 }
 
-impl TryInto<usize> for Types {
-    type Error = String;
+impl TryFrom<Types> for usize {
+    type Error = Error;
 
-    fn try_into(self) -> Result<usize, Self::Error> {
-        match self {
+    fn try_from(t: Types) -> Result<usize, Self::Error> {
+        match t {
             Types::UInteger(t) => Ok(t),
-            _ => Err(format!("Could not convert {:?} to usize", self)),
+            _ => Err(t.mismatch("UInteger")),
         }
     }
 }
 
-impl TryInto<char> for Types {
-    type Error = String;
+impl TryFrom<Types> for char {
+    type Error = Error;
 
-    fn try_into(self) -> Result<char, Self::Error> {
-        match self {
+    fn try_from(t: Types) -> Result<char, Self::Error> {
+        match t {
             Types::Char(t) => Ok(t),
-            _ => Err(format!("Could not convert {:?} to char", self)),
+            _ => Err(t.mismatch("Char")),
         }
     }
 }
 
-impl TryInto<f64> for Types {
-    type Error = String;
+impl TryFrom<Types> for f64 {
+    type Error = Error;
 
-    fn try_into(self) -> Result<f64, Self::Error> {
-        match self {
+    fn try_from(t: Types) -> Result<f64, Self::Error> {
+        match t {
             Types::Float(t) => Ok(t),
-            _ => Err(format!("Could not convert {:?} to f64", self)),
+            _ => Err(t.mismatch("Float")),
         }
     }
 }
 
-impl TryInto<bool> for Types {
-    type Error = String;
+impl TryFrom<Types> for bool {
+    type Error = Error;
 
-    fn try_into(self) -> Result<bool, Self::Error> {
-        match self {
+    fn try_from(t: Types) -> Result<bool, Self::Error> {
+        match t {
             Types::Boolean(t) => Ok(t),
-            _ => Err(format!("Could not convert {:?} to bool", self)),
+            _ => Err(t.mismatch("Boolean")),
         }
     }
 }
 
-impl TryInto<String> for Types {
-    type Error = String;
+impl TryFrom<Types> for String {
+    type Error = Error;
 
-    fn try_into(self) -> Result<String, Self::Error> {
-        match self {
+    fn try_from(t: Types) -> Result<String, Self::Error> {
+        match t {
             Types::String(t) => Ok(t),
-            _ => Err(format!("Could not convert {:?} to String", self)),
+            _ => Err(t.mismatch("String")),
         }
     }
 }
 
-impl<T: TryFrom<Types>> TryInto<(String, T)> for Types {
-    type Error = String;
+impl<T> TryInto<(String, T)> for Types
+where
+    T: TryFrom<Types>,
+    T::Error: Into<Error>,
+{
+    type Error = Error;
 
     fn try_into(self) -> Result<(String, T), Self::Error> {
-        let err = format!("Could not convert {:?} to KeyValue", self);
         match self {
-            Types::KeyValue(k, v) => Ok((k, (*v).try_into().map_err(|_| err.clone())?)),
-            _ => Err(format!("Could not convert {:?} to KeyValue", self)),
+            Types::KeyValue(k, v) => Ok((k, (*v).try_into().map_err(Into::into)?)),
+            _ => Err(self.mismatch("KeyValue")),
         }
     }
 }
 
-impl<T: TryFrom<Types>> TryInto<Vec<T>> for Types {
-    type Error = String;
+impl<T> TryInto<Vec<T>> for Types
+where
+    T: TryFrom<Types>,
+    T::Error: Into<Error>,
+{
+    type Error = Error;
 
     fn try_into(self) -> Result<Vec<T>, Self::Error> {
-        let err = format!("Could not convert {:?} to Vec<T>", self);
         match self {
             Types::Vector(t) => t
                 .into_iter()
-                .map(|e| e.try_into().map_err(|_| err.clone()))
-                .collect::<Result<Vec<T>, String>>(),
-            _ => Err(err),
+                .map(|e| e.try_into().map_err(Into::into))
+                .collect(),
+            _ => Err(self.mismatch("Vector")),
         }
     }
 }
 
-impl<T: TryFrom<Types>> TryInto<HashMap<String, T>> for Types {
-    type Error = String;
+impl<T> TryInto<HashMap<String, T>> for Types
+where
+    T: TryFrom<Types>,
+    T::Error: Into<Error>,
+{
+    type Error = Error;
 
     fn try_into(self) -> Result<HashMap<String, T>, Self::Error> {
-        let err = format!("Could not convert {:?} to HashMap<String, T>", self);
         match self {
-            Types::HashMap(t) => {
-                let mut has_error = false;
-                let hm = t
-                    .into_iter()
-                    .map(|(k, v)| (k, v.try_into().map_err(|_| err.clone())))
-                    .fold(
-                        HashMap::new(),
-                        |mut acc, (k, v): (String, Result<T, String>)| {
-                            if let Ok(t) = v {
-                                acc.insert(k, t);
-                            } else {
-                                has_error = true;
-                            }
-                            acc
-                        },
-                    );
-
-                if has_error {
-                    Err(err)
-                } else {
-                    Ok(hm)
-                }
-            }
-            _ => Err(err),
+            Types::HashMap(t) => t
+                .into_iter()
+                .map(|(k, v)| Ok((k, v.try_into().map_err(Into::into)?)))
+                .collect(),
+            _ => Err(self.mismatch("HashMap")),
         }
     }
 }
 
-impl<T: TryFrom<Types>> TryInto<BTreeMap<String, T>> for Types {
-    type Error = String;
+impl<T> TryInto<BTreeMap<String, T>> for Types
+where
+    T: TryFrom<Types>,
+    T::Error: Into<Error>,
+{
+    type Error = Error;
 
     fn try_into(self) -> Result<BTreeMap<String, T>, Self::Error> {
-        let err = format!("Could not convert {:?} to BTreeMap<String, T>", self);
         match self {
-            Types::BTreeMap(t) => {
-                let mut has_error = false;
-                let hm = t
-                    .into_iter()
-                    .map(|(k, v)| (k, v.try_into().map_err(|_| err.clone())))
-                    .fold(
-                        BTreeMap::new(),
-                        |mut acc, (k, v): (String, Result<T, String>)| {
-                            if let Ok(t) = v {
-                                acc.insert(k, t);
-                            } else {
-                                has_error = true;
-                            }
-                            acc
-                        },
-                    );
-
-                if has_error {
-                    Err(err)
-                } else {
-                    Ok(hm)
-                }
-            }
-            _ => Err(err),
+            Types::BTreeMap(t) => t
+                .into_iter()
+                .map(|(k, v)| Ok((k, v.try_into().map_err(Into::into)?)))
+                .collect(),
+            _ => Err(self.mismatch("BTreeMap")),
         }
     }
 }
@@ -0,0 +1,458 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    future::poll_fn,
+    ops::{Bound, RangeBounds},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use futures_core::Stream;
+use tokio::sync::Semaphore;
+
+use crate::model::{Key, Types, Value};
+use crate::prefix_range;
+
+/// Default number of events a `Subscription` buffers before its `Backpressure` policy is applied.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// The kind of change a `ChangeEvent` describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The key did not exist and a value was inserted.
+    Insert,
+    /// The key already existed and its value was replaced or modified.
+    Update,
+    /// The key and its value were removed.
+    Remove,
+}
+
+/// `ChangeEvent` describes a change applied to a key by a method like `insert`, `get_mut`, `compare_and_swap` or `remove`.
+/// `old` is the value before the change and `new` is the value after it, `None` means the key had no value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent<K = String, V = Types> {
+    pub key: K,
+    pub old: Option<V>,
+    pub new: Option<V>,
+    pub kind: ChangeKind,
+}
+
+impl<K, V> ChangeEvent<K, V> {
+    fn new(key: K, old: Option<V>, new: Option<V>) -> Self {
+        let kind = match (&old, &new) {
+            (_, None) => ChangeKind::Remove,
+            (None, Some(_)) => ChangeKind::Insert,
+            (Some(_), Some(_)) => ChangeKind::Update,
+        };
+
+        Self {
+            key,
+            old,
+            new,
+            kind,
+        }
+    }
+}
+
+/// A change applied to a key as `(key, old, new)`, used before it is turned into a `ChangeEvent`.
+pub(crate) type Change<K, V> = (K, Option<V>, Option<V>);
+
+/// `Event` is the item yielded by a `Subscription`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event<K = String, V = Types> {
+    /// A watched key was changed.
+    Change(ChangeEvent<K, V>),
+    /// Watched keys were changed together by `BTree::apply`, `clear`, `extend`, `retain` or `drain_range`,
+    /// only the changes to watched keys are included.
+    Batch(Vec<ChangeEvent<K, V>>),
+    /// The subscription could not keep up and `usize` events were discarded by its `Backpressure` policy.
+    /// The consumer should resync its state with the `BTree`.
+    Lagged(usize),
+    /// The `BTree` stopped, this is the last event received by the `Subscription`.
+    Shutdown,
+}
+
+impl<K, V> Event<K, V> {
+    fn is_change(&self) -> bool {
+        matches!(self, Event::Change(_) | Event::Batch(_))
+    }
+}
+
+/// `Backpressure` is the policy applied when a `Subscription` is not consuming events as fast as the `BTree` produces them.
+/// None of the policies, except `Block`, can stall the `BTree` thread. A capacity of 0 is treated as 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Buffers up to `usize` events, discarding the oldest ones when full and reporting them as `Event::Lagged`.
+    DropOldest(usize),
+    /// Buffers up to `usize` events, discarding new ones when full and reporting them as `Event::Lagged`.
+    DropNewest(usize),
+    /// Buffers a single event per key, merging a new change into the pending one so only the latest value is delivered.
+    /// `Event::Batch` events are split into their changes, so they are coalesced as well.
+    Coalesce,
+    /// Buffers up to `usize` events and makes the `BTree` thread wait for the consumer when full.
+    /// Every other operation on the `BTree` waits as well, so it should only be used with fast consumers.
+    Block(usize),
+}
+
+impl Backpressure {
+    // a capacity of 0 would make `Block` wait forever and `DropOldest` report a lag before every event.
+    fn clamped(self) -> Self {
+        match self {
+            Backpressure::DropOldest(capacity) => Backpressure::DropOldest(capacity.max(1)),
+            Backpressure::DropNewest(capacity) => Backpressure::DropNewest(capacity.max(1)),
+            Backpressure::Block(capacity) => Backpressure::Block(capacity.max(1)),
+            Backpressure::Coalesce => Backpressure::Coalesce,
+        }
+    }
+}
+
+impl Default for Backpressure {
+    fn default() -> Self {
+        Backpressure::DropOldest(DEFAULT_CAPACITY)
+    }
+}
+
+/// `KeyFilter` selects which keys a `Subscription` is interested in.
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyFilter<K = String> {
+    /// A single key.
+    Key(K),
+    /// Every key inside the range, compared with the same ordering used by the `BTree`.
+    Range(Bound<K>, Bound<K>),
+    /// Every key.
+    All,
+}
+
+impl<K: Key> KeyFilter<K> {
+    /// Creates a `KeyFilter::Range` from any range of `K`, like `"a".to_string().."n".to_string()`.
+    pub fn range<R: RangeBounds<K>>(range: R) -> Self {
+        KeyFilter::Range(range.start_bound().cloned(), range.end_bound().cloned())
+    }
+
+    fn matches(&self, key: &K) -> bool {
+        match self {
+            KeyFilter::All => true,
+            KeyFilter::Key(k) => k == key,
+            KeyFilter::Range(start, end) => (start.as_ref(), end.as_ref()).contains(key),
+        }
+    }
+}
+
+impl KeyFilter<String> {
+    /// Creates a `KeyFilter::Range` containing every key starting with `prefix`, like `"user:"`.
+    pub fn prefix(prefix: &str) -> Self {
+        let (start, end) = prefix_range(prefix);
+        KeyFilter::Range(start, end)
+    }
+}
+
+struct Queue<K, V> {
+    events: VecDeque<Event<K, V>>,
+    // number of `Event::Change` and `Event::Batch` in `events` and `changes`, `Event::Lagged` markers are not counted.
+    len: usize,
+    // only used by `Backpressure::Coalesce`, the pending changes ordered by their last change,
+    // `slots` indexes them by key so a new change is merged without scanning the queue.
+    changes: BTreeMap<u64, ChangeEvent<K, V>>,
+    slots: BTreeMap<K, u64>,
+    next_slot: u64,
+    waker: Option<Waker>,
+    closed: bool,
+    dropped: bool,
+}
+
+impl<K: Key, V> Queue<K, V> {
+    fn push(&mut self, policy: Backpressure, event: Event<K, V>) {
+        match (policy, event) {
+            (Backpressure::DropOldest(capacity), event) if self.len >= capacity => {
+                let mut lagged = 0;
+                while lagged == 0 || self.len >= capacity {
+                    match self.events.pop_front() {
+                        Some(Event::Lagged(n)) => lagged += n,
+                        Some(Event::Change(_)) | Some(Event::Batch(_)) => {
+                            lagged += 1;
+                            self.len -= 1;
+                        }
+                        Some(Event::Shutdown) | None => break,
+                    }
+                }
+                self.events.push_front(Event::Lagged(lagged));
+                self.push_back(event);
+            }
+            (Backpressure::DropNewest(capacity), _) if self.len >= capacity => {
+                if let Some(Event::Lagged(n)) = self.events.back_mut() {
+                    *n += 1;
+                } else {
+                    self.events.push_back(Event::Lagged(1));
+                }
+            }
+            (Backpressure::Coalesce, Event::Change(event)) => self.coalesce(event),
+            (Backpressure::Coalesce, Event::Batch(events)) => {
+                for event in events {
+                    self.coalesce(event);
+                }
+            }
+            (_, event) => self.push_back(event),
+        }
+
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn coalesce(&mut self, event: ChangeEvent<K, V>) {
+        let pending = self
+            .slots
+            .remove(&event.key)
+            .and_then(|slot| self.changes.remove(&slot));
+        let event = match pending {
+            Some(change) => {
+                self.len -= 1;
+                // a key inserted and removed before being received has no change left.
+                if change.old.is_none() && event.new.is_none() {
+                    return;
+                }
+                ChangeEvent::new(event.key, change.old, event.new)
+            }
+            None => event,
+        };
+
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.slots.insert(event.key.clone(), slot);
+        self.changes.insert(slot, event);
+        self.len += 1;
+    }
+
+    fn pop_front(&mut self) -> Option<Event<K, V>> {
+        match self.changes.pop_first() {
+            Some((_, change)) => {
+                self.slots.remove(&change.key);
+                Some(Event::Change(change))
+            }
+            None => self.events.pop_front(),
+        }
+    }
+
+    fn push_back(&mut self, event: Event<K, V>) {
+        if event.is_change() {
+            self.len += 1;
+        }
+        self.events.push_back(event);
+    }
+}
+
+struct Shared<K, V> {
+    queue: Mutex<Queue<K, V>>,
+    // only used by `Backpressure::Block`, holds one permit per free slot in the queue.
+    permits: Semaphore,
+}
+
+/// `Subscription` is the receiving end of `BTree::watch`, `BTree::watch_range`, `BTree::watch_prefix`, `BTree::changes` and `BTree::subscribe`.
+/// It yields an `Event` for every change applied to the watched keys, in the order the changes were applied.
+/// `Subscription` implements `futures::Stream`, so it can be consumed with `StreamExt` combinators.
+pub struct Subscription<K = String, V = Types> {
+    shared: Arc<Shared<K, V>>,
+    policy: Backpressure,
+}
+
+impl<K: Key, V: Value> Subscription<K, V> {
+    pub(crate) fn new(filter: KeyFilter<K>, policy: Backpressure) -> (Observer<K, V>, Self) {
+        let policy = policy.clamped();
+        let permits = match policy {
+            Backpressure::Block(capacity) => capacity,
+            _ => 0,
+        };
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                events: VecDeque::new(),
+                len: 0,
+                changes: BTreeMap::new(),
+                slots: BTreeMap::new(),
+                next_slot: 0,
+                waker: None,
+                closed: false,
+                dropped: false,
+            }),
+            permits: Semaphore::new(permits),
+        });
+        let observer = Observer {
+            filter,
+            policy,
+            shared: shared.clone(),
+        };
+
+        (observer, Self { shared, policy })
+    }
+
+    /// Method `recv` waits for the next `Event`.
+    /// It returns `None` if the `BTree` is no longer running and every pending event was received.
+    pub async fn recv(&mut self) -> Option<Event<K, V>> {
+        poll_fn(|cx| self.poll_event(cx)).await
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<Event<K, V>>> {
+        let mut queue = self.shared.queue.lock().unwrap();
+        match queue.pop_front() {
+            Some(event) => {
+                if event.is_change() {
+                    queue.len -= 1;
+                    if let Backpressure::Block(_) = self.policy {
+                        self.shared.permits.add_permits(1);
+                    }
+                }
+                Poll::Ready(Some(event))
+            }
+            None if queue.closed => Poll::Ready(None),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<K: Key, V: Value> Stream for Subscription<K, V> {
+    type Item = Event<K, V>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_event(cx)
+    }
+}
+
+impl<K, V> Drop for Subscription<K, V> {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().dropped = true;
+        self.shared.permits.close();
+    }
+}
+
+/// `Observer` is the sending end of a `Subscription`, owned by the `BTree` thread.
+pub(crate) struct Observer<K, V> {
+    filter: KeyFilter<K>,
+    policy: Backpressure,
+    shared: Arc<Shared<K, V>>,
+}
+
+impl<K: Key, V: Value> Observer<K, V> {
+    fn is_dropped(&self) -> bool {
+        self.shared.queue.lock().unwrap().dropped
+    }
+
+    async fn send(&self, event: Event<K, V>) -> bool {
+        if let Backpressure::Block(_) = self.policy {
+            match self.shared.permits.acquire().await {
+                Ok(permit) => permit.forget(),
+                Err(_) => return false,
+            }
+        }
+
+        let mut queue = self.shared.queue.lock().unwrap();
+        if queue.dropped {
+            return false;
+        }
+        queue.push(self.policy, event);
+        true
+    }
+}
+
+impl<K, V> Drop for Observer<K, V> {
+    fn drop(&mut self) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.events.push_back(Event::Shutdown);
+        queue.closed = true;
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// `Observers` is owned by the `BTree` thread and dispatches `ChangeEvent`s to every `Subscription` interested in them.
+pub(crate) struct Observers<K, V> {
+    observers: Vec<Observer<K, V>>,
+}
+
+impl<K, V> Default for Observers<K, V> {
+    fn default() -> Self {
+        Self {
+            observers: Vec::new(),
+        }
+    }
+}
+
+impl<K: Key, V: Value> Observers<K, V> {
+    pub(crate) fn watch(&mut self, observer: Observer<K, V>) {
+        self.observers.push(observer);
+    }
+
+    /// Closes every `Subscription`, which receive `Event::Shutdown` after their pending events.
+    pub(crate) fn shutdown(self) {
+        drop(self.observers);
+    }
+
+    /// Checks if any `Subscription` is interested in `key`, so the values of a change only need to be copied when it is.
+    pub(crate) fn is_watched(&self, key: &K) -> bool {
+        self.observers.iter().any(|o| o.filter.matches(key))
+    }
+
+    pub(crate) async fn notify(&mut self, key: &K, old: Option<V>, new: Option<V>) {
+        if !self.is_watched(key) {
+            return;
+        }
+
+        let event = ChangeEvent::new(key.clone(), old, new);
+        self.dispatch(|filter| {
+            if filter.matches(&event.key) {
+                Some(Event::Change(event.clone()))
+            } else {
+                None
+            }
+        })
+        .await;
+    }
+
+    /// Sends the `changes` applied together as a single `Event::Batch` to every interested `Subscription`.
+    /// Callers only need to include the changes to watched keys.
+    pub(crate) async fn notify_batch(&mut self, changes: Vec<Change<K, V>>) {
+        if changes.is_empty() {
+            return;
+        }
+
+        let changes: Vec<ChangeEvent<K, V>> = changes
+            .into_iter()
+            .map(|(key, old, new)| ChangeEvent::new(key, old, new))
+            .collect();
+
+        self.dispatch(|filter| {
+            let matching: Vec<ChangeEvent<K, V>> = changes
+                .iter()
+                .filter(|change| filter.matches(&change.key))
+                .cloned()
+                .collect();
+            if matching.is_empty() {
+                None
+            } else {
+                Some(Event::Batch(matching))
+            }
+        })
+        .await;
+    }
+
+    async fn dispatch<F>(&mut self, event_for: F)
+    where
+        F: Fn(&KeyFilter<K>) -> Option<Event<K, V>>,
+    {
+        let mut dropped = Vec::new();
+        for (i, observer) in self.observers.iter().enumerate() {
+            let alive = match event_for(&observer.filter) {
+                Some(event) => observer.send(event).await,
+                None => !observer.is_dropped(),
+            };
+            if !alive {
+                dropped.push(i);
+            }
+        }
+        for i in dropped.into_iter().rev() {
+            self.observers.remove(i);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use crate::logic::{try_apply_with, Registry};
+use crate::model::{Operation, Types};
+use crate::Error;
+
+/// A step of a `Path`, a key of a `HashMap` or `BTreeMap` or an index of a `Vector`.
+/// A `Key` made of digits can also index a `Vector` and an `Index` can also address the map key with the same digits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+impl From<&str> for Segment {
+    fn from(k: &str) -> Self {
+        Segment::Key(k.to_owned())
+    }
+}
+
+impl From<String> for Segment {
+    fn from(k: String) -> Self {
+        Segment::Key(k)
+    }
+}
+
+impl From<usize> for Segment {
+    fn from(i: usize) -> Self {
+        Segment::Index(i)
+    }
+}
+
+/// `Path` addresses an element nested inside a `Types` value, like `Path::new().key("config").key("limits").index(3)`.
+/// It can also be parsed from a JSON pointer like `"/config/limits/3"`, where `~1` is read as `/` and `~0` as `~`.
+/// An empty path addresses the value itself.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Path {
+    segments: Vec<Segment>,
+}
+
+impl Path {
+    /// Creates an empty `Path`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a map key to the path.
+    pub fn key<T: Into<String>>(mut self, k: T) -> Self {
+        self.segments.push(Segment::Key(k.into()));
+        self
+    }
+
+    /// Adds a `Vector` index to the path.
+    pub fn index(mut self, i: usize) -> Self {
+        self.segments.push(Segment::Index(i));
+        self
+    }
+
+    /// Returns the segments of the path.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+impl From<Vec<Segment>> for Path {
+    fn from(segments: Vec<Segment>) -> Self {
+        Self { segments }
+    }
+}
+
+impl From<&str> for Path {
+    fn from(pointer: &str) -> Self {
+        if pointer.is_empty() {
+            return Self::default();
+        }
+        let pointer = pointer.strip_prefix('/').unwrap_or(pointer);
+        pointer
+            .split('/')
+            .map(|s| Segment::Key(s.replace("~1", "/").replace("~0", "~")))
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+impl From<String> for Path {
+    fn from(pointer: String) -> Self {
+        Path::from(pointer.as_str())
+    }
+}
+
+impl Segment {
+    fn as_key(&self) -> String {
+        match self {
+            Segment::Key(k) => k.to_owned(),
+            Segment::Index(i) => i.to_string(),
+        }
+    }
+
+    fn as_index(&self) -> Option<usize> {
+        match self {
+            Segment::Key(k) => k.parse().ok(),
+            Segment::Index(i) => Some(*i),
+        }
+    }
+}
+
+fn child<'a>(x: &'a Types, segment: &Segment) -> Option<&'a Types> {
+    match x {
+        Types::HashMap(xx) => xx.get(&segment.as_key()),
+        Types::BTreeMap(xx) => xx.get(&segment.as_key()),
+        Types::Vector(xx) => xx.get(segment.as_index()?),
+        _ => None,
+    }
+}
+
+fn child_mut<'a>(x: &'a mut Types, segment: &Segment) -> Result<&'a mut Types, Error> {
+    match x {
+        Types::HashMap(xx) => xx.get_mut(&segment.as_key()).ok_or(Error::NotFound),
+        Types::BTreeMap(xx) => xx.get_mut(&segment.as_key()).ok_or(Error::NotFound),
+        Types::Vector(xx) => {
+            let len = xx.len();
+            let index = segment.as_index().ok_or(Error::NotFound)?;
+            xx.get_mut(index)
+                .ok_or(Error::IndexOutOfBounds { index, len })
+        }
+        _ => Err(Error::NotFound),
+    }
+}
+
+/// Returns the element of `x` at `path`, or `None` if the path does not exist.
+pub(crate) fn get<'a>(x: &'a Types, path: &Path) -> Option<&'a Types> {
+    path.segments
+        .iter()
+        .try_fold(x, |x, segment| child(x, segment))
+}
+
+/// Applies `op` with `v` to the element of `x` at `path` and returns the updated element.
+/// `Operation::Replace` also inserts a missing last key in a map. `x` is not modified when it fails.
+pub(crate) fn apply(
+    x: &mut Types,
+    path: &Path,
+    v: Types,
+    op: Operation,
+    operations: &Registry,
+) -> Result<Types, Error> {
+    let (last, parents) = match path.segments.split_last() {
+        Some(split) => split,
+        None => {
+            try_apply_with(x, v, op, operations)?;
+            return Ok(x.clone());
+        }
+    };
+    let parent = parents
+        .iter()
+        .try_fold(x, |x, segment| child_mut(x, segment))?;
+
+    let missing_key = match parent {
+        Types::HashMap(xx) => !xx.contains_key(&last.as_key()),
+        Types::BTreeMap(xx) => !xx.contains_key(&last.as_key()),
+        _ => false,
+    };
+    if missing_key && op == Operation::Replace {
+        match parent {
+            Types::HashMap(xx) => xx.insert(last.as_key(), v.clone()),
+            Types::BTreeMap(xx) => xx.insert(last.as_key(), v.clone()),
+            _ => None,
+        };
+        return Ok(v);
+    }
+
+    let element = child_mut(parent, last)?;
+    try_apply_with(element, v, op, operations)?;
+    Ok(element.clone())
+}
+
+/// Removes the element of `x` at `path` and returns it. The path must not be empty.
+pub(crate) fn remove(x: &mut Types, path: &Path) -> Result<Types, Error> {
+    let (last, parents) = path.segments.split_last().ok_or(Error::NotFound)?;
+    let parent = parents
+        .iter()
+        .try_fold(x, |x, segment| child_mut(x, segment))?;
+
+    match parent {
+        Types::HashMap(xx) => xx.remove(&last.as_key()).ok_or(Error::NotFound),
+        Types::BTreeMap(xx) => xx.remove(&last.as_key()).ok_or(Error::NotFound),
+        Types::Vector(xx) => {
+            let len = xx.len();
+            let index = last.as_index().ok_or(Error::NotFound)?;
+            if index < len {
+                Ok(xx.remove(index))
+            } else {
+                Err(Error::IndexOutOfBounds { index, len })
+            }
+        }
+        _ => Err(Error::NotFound),
+    }
+}
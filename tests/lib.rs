@@ -1,4 +1,20 @@
-use observable_btree::{model::Types, BTree};
+use futures::{FutureExt, StreamExt};
+use observable_btree::{
+    batch::Batch,
+    logic::Registry,
+    model::{Operation, Types},
+    observer::{Backpressure, ChangeEvent, ChangeKind, Event, KeyFilter},
+    path::Path,
+    BTree, Error,
+};
+use std::{collections::BTreeMap, convert::TryInto};
+
+fn change<K: std::fmt::Debug, V: std::fmt::Debug>(event: Option<Event<K, V>>) -> ChangeEvent<K, V> {
+    match event {
+        Some(Event::Change(change)) => change,
+        e => panic!("expected a change event, got {:?}", e),
+    }
+}
 
 #[tokio::test]
 async fn test_insert_contains() {
@@ -84,10 +100,7 @@ async fn test_remove_entry() {
 
     let remove = btree.remove_entry("hello".to_string()).await;
     let remove_kv = remove.unwrap().unwrap();
-    assert_eq!(
-        remove_kv,
-        Types::KeyValue("hello".to_string(), Box::new(Types::Integer(5)))
-    );
+    assert_eq!(remove_kv, ("hello".to_string(), Types::Integer(5)));
 }
 
 #[tokio::test]
@@ -123,3 +136,1283 @@ async fn test_insert_getmut() {
     let get_int = get.unwrap().unwrap();
     assert_eq!(get_int, Types::Integer(4));
 }
+
+#[tokio::test]
+async fn test_watch() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree.watch("hello".to_string()).await.unwrap();
+
+    let ins = btree.insert("hello".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+
+    let ins = btree.insert("wow".to_string(), 7).await;
+    assert!(ins.unwrap().is_none());
+
+    let get_mut = btree
+        .get_mut(
+            "hello".to_string(),
+            5,
+            observable_btree::model::Operation::Add,
+        )
+        .await;
+    assert!(get_mut.unwrap());
+
+    let remove = btree.remove("hello".to_string()).await;
+    assert!(remove.unwrap().is_some());
+
+    let event = change(watch.recv().await);
+    assert_eq!(event.kind, ChangeKind::Insert);
+    assert_eq!(event.old, None);
+    assert_eq!(event.new, Some(Types::Integer(5)));
+
+    let event = change(watch.recv().await);
+    assert_eq!(event.kind, ChangeKind::Update);
+    assert_eq!(event.old, Some(Types::Integer(5)));
+    assert_eq!(event.new, Some(Types::Integer(10)));
+
+    let event = change(watch.recv().await);
+    assert_eq!(event.kind, ChangeKind::Remove);
+    assert_eq!(event.key, "hello".to_string());
+    assert_eq!(event.old, Some(Types::Integer(10)));
+    assert_eq!(event.new, None);
+}
+
+#[tokio::test]
+async fn test_watch_range() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree
+        .watch_range("b".to_string().."d".to_string())
+        .await
+        .unwrap();
+
+    for key in &["a", "b", "c", "d"] {
+        let ins = btree.insert(key.to_string(), 5).await;
+        assert!(ins.unwrap().is_none());
+    }
+
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "b".to_string());
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "c".to_string());
+
+    let remove = btree.remove("a".to_string()).await;
+    assert!(remove.unwrap().is_some());
+    let remove = btree.remove("c".to_string()).await;
+    assert!(remove.unwrap().is_some());
+
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "c".to_string());
+    assert_eq!(event.kind, ChangeKind::Remove);
+}
+
+#[tokio::test]
+async fn test_watch_prefix() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree.watch_prefix("user:").await.unwrap();
+
+    let ins = btree.insert("session:1".to_string(), 1).await;
+    assert!(ins.unwrap().is_none());
+    let ins = btree.insert("user:1".to_string(), 2).await;
+    assert!(ins.unwrap().is_none());
+    let ins = btree.insert("user:2".to_string(), 3).await;
+    assert!(ins.unwrap().is_none());
+
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "user:1".to_string());
+    assert_eq!(event.new, Some(Types::Integer(2)));
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "user:2".to_string());
+    assert_eq!(event.new, Some(Types::Integer(3)));
+}
+
+#[tokio::test]
+async fn test_changes_stream() {
+    let btree = BTree::start(1000);
+
+    let changes = btree.changes().await.unwrap();
+
+    let ins = btree.insert("hello".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+    let ins = btree.insert("wow".to_string(), 7).await;
+    assert!(ins.unwrap().is_none());
+    let remove = btree.remove("hello".to_string()).await;
+    assert!(remove.unwrap().is_some());
+
+    let events = changes
+        .take(3)
+        .map(|e| change(Some(e)))
+        .map(|e| (e.key, e.kind))
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        events,
+        vec![
+            ("hello".to_string(), ChangeKind::Insert),
+            ("wow".to_string(), ChangeKind::Insert),
+            ("hello".to_string(), ChangeKind::Remove),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_subscribe_drop_oldest() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree
+        .subscribe(KeyFilter::All, Backpressure::DropOldest(2))
+        .await
+        .unwrap();
+
+    for i in 0..5 {
+        let ins = btree.insert(i.to_string(), i).await;
+        assert!(ins.unwrap().is_none());
+    }
+
+    assert_eq!(watch.recv().await, Some(Event::Lagged(3)));
+    assert_eq!(change(watch.recv().await).key, "3".to_string());
+    assert_eq!(change(watch.recv().await).key, "4".to_string());
+}
+
+#[tokio::test]
+async fn test_subscribe_drop_newest() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree
+        .subscribe(KeyFilter::All, Backpressure::DropNewest(2))
+        .await
+        .unwrap();
+
+    for i in 0..5 {
+        let ins = btree.insert(i.to_string(), i).await;
+        assert!(ins.unwrap().is_none());
+    }
+
+    assert_eq!(change(watch.recv().await).key, "0".to_string());
+    assert_eq!(change(watch.recv().await).key, "1".to_string());
+    assert_eq!(watch.recv().await, Some(Event::Lagged(3)));
+}
+
+#[tokio::test]
+async fn test_subscribe_coalesce() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree
+        .subscribe(KeyFilter::All, Backpressure::Coalesce)
+        .await
+        .unwrap();
+
+    for i in 0..5 {
+        let _ = btree.insert("hello".to_string(), i).await.unwrap();
+    }
+    let ins = btree.insert("wow".to_string(), 7).await;
+    assert!(ins.unwrap().is_none());
+
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "hello".to_string());
+    assert_eq!(event.kind, ChangeKind::Insert);
+    assert_eq!(event.new, Some(Types::Integer(4)));
+    assert_eq!(change(watch.recv().await).key, "wow".to_string());
+
+    // batches are split into their changes and coalesced with the pending ones
+    btree
+        .extend(vec![("hello".to_string(), 8), ("new".to_string(), 9)])
+        .await
+        .unwrap();
+    btree
+        .extend(vec![("hello".to_string(), 10), ("new".to_string(), 11)])
+        .await
+        .unwrap();
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "hello".to_string());
+    assert_eq!(event.old, Some(Types::Integer(4)));
+    assert_eq!(event.new, Some(Types::Integer(10)));
+    let event = change(watch.recv().await);
+    assert_eq!(event.key, "new".to_string());
+    assert_eq!(event.kind, ChangeKind::Insert);
+    assert_eq!(event.new, Some(Types::Integer(11)));
+    assert!(watch.recv().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn test_subscribe_coalesce_large_batch() {
+    let btree = BTree::<u64, u64>::spawn(1000);
+
+    let mut watch = btree
+        .subscribe(KeyFilter::All, Backpressure::Coalesce)
+        .await
+        .unwrap();
+
+    btree.extend((0..50_000).map(|i| (i, i))).await.unwrap();
+    btree.extend((0..50_000).map(|i| (i, i + 1))).await.unwrap();
+
+    for i in 0..50_000 {
+        let event = change(watch.recv().await);
+        assert_eq!(event.key, i);
+        assert_eq!(event.kind, ChangeKind::Insert);
+        assert_eq!(event.new, Some(i + 1));
+    }
+    assert!(watch.recv().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn test_subscribe_zero_capacity() {
+    let btree = BTree::start(1000);
+
+    let mut block = btree
+        .subscribe(KeyFilter::All, Backpressure::Block(0))
+        .await
+        .unwrap();
+    let mut drop_oldest = btree
+        .subscribe(KeyFilter::All, Backpressure::DropOldest(0))
+        .await
+        .unwrap();
+
+    let ins = btree.insert("hello".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+    assert_eq!(btree.len().await.unwrap(), 1);
+
+    assert_eq!(change(block.recv().await).key, "hello".to_string());
+    assert_eq!(change(drop_oldest.recv().await).key, "hello".to_string());
+    assert!(drop_oldest.recv().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn test_subscribe_block() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree
+        .subscribe(KeyFilter::All, Backpressure::Block(1))
+        .await
+        .unwrap();
+
+    let ins = btree.insert("hello".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+
+    let blocked = tokio::time::timeout(
+        std::time::Duration::from_millis(50),
+        btree.insert("wow".to_string(), 7),
+    )
+    .await;
+    assert!(blocked.is_err());
+
+    assert_eq!(change(watch.recv().await).key, "hello".to_string());
+    assert_eq!(change(watch.recv().await).key, "wow".to_string());
+}
+
+#[tokio::test]
+async fn test_wait_for() {
+    let btree = BTree::start(1000);
+
+    let writer = btree.clone();
+    tokio::spawn(async move {
+        for i in 0..10 {
+            let _ = writer.insert("counter".to_string(), i).await.unwrap();
+        }
+    });
+
+    let value = btree
+        .wait_for(
+            "counter".to_string(),
+            |v| *v == Types::Integer(7),
+            Some(std::time::Duration::from_secs(1)),
+        )
+        .await;
+    assert_eq!(value.unwrap(), Types::Integer(7));
+
+    let timeout = btree
+        .wait_for(
+            "missing".to_string(),
+            |_| true,
+            Some(std::time::Duration::from_millis(10)),
+        )
+        .await;
+    assert_eq!(timeout, Err(Error::Timeout));
+}
+
+#[tokio::test]
+async fn test_type_mismatch() {
+    let btree = BTree::start(1000);
+
+    let ins = btree.insert("hello".to_string(), true).await;
+    assert!(ins.unwrap().is_none());
+
+    let get = btree.get("hello".to_string()).await.unwrap().unwrap();
+    let int: Result<isize, Error> = get.try_into();
+    assert_eq!(
+        int,
+        Err(Error::TypeMismatch {
+            expected: "Integer",
+            actual: "Boolean"
+        })
+    );
+
+    // a failing element reports its own mismatch
+    let vector = Types::from(vec![Types::Integer(1), Types::Boolean(true)]);
+    let ints: Result<Vec<isize>, Error> = vector.clone().try_into();
+    assert_eq!(
+        ints,
+        Err(Error::TypeMismatch {
+            expected: "Integer",
+            actual: "Boolean"
+        })
+    );
+    let values: Result<Vec<Types>, Error> = vector.try_into();
+    assert_eq!(values.unwrap().len(), 2);
+
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), Types::String("x".to_string()));
+    let floats: Result<BTreeMap<String, f64>, Error> = Types::BTreeMap(map).try_into();
+    assert_eq!(
+        floats,
+        Err(Error::TypeMismatch {
+            expected: "Float",
+            actual: "String"
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_shutdown() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree.watch("hello".to_string()).await.unwrap();
+
+    let ins = btree.insert("hello".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+
+    btree.shutdown().await;
+
+    assert_eq!(change(watch.recv().await).key, "hello".to_string());
+    assert_eq!(watch.recv().await, Some(Event::Shutdown));
+    assert_eq!(watch.recv().await, None);
+
+    let ins = btree.insert("wow".to_string(), 5).await;
+    assert_eq!(ins, Err(Error::ActorStopped));
+}
+
+#[tokio::test]
+async fn test_drop_stops_btree() {
+    let btree = BTree::start(1000);
+
+    let mut watch = btree.changes().await.unwrap();
+    drop(btree);
+
+    assert_eq!(watch.recv().await, Some(Event::Shutdown));
+    assert_eq!(watch.recv().await, None);
+}
+
+#[tokio::test]
+async fn test_clone_handles() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let btree = BTree::start(1000);
+    assert_send_sync(&btree);
+    assert_eq!(btree.handle_count(), 1);
+
+    let btree_async = btree.clone();
+    assert_eq!(btree.handle_count(), 2);
+
+    tokio::spawn(async move {
+        let ins = btree_async.insert("hello".to_string(), 5).await;
+        assert!(ins.unwrap().is_none());
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(btree.handle_count(), 1);
+    let get = btree.get("hello".to_string()).await;
+    assert_eq!(get.unwrap(), Some(Types::Integer(5)));
+
+    // an `Iter` holds a single handle, also while fetching its pages
+    let mut iter = btree.iter(1);
+    assert_eq!(btree.handle_count(), 2);
+    let page = iter.next_page().await.unwrap();
+    assert_eq!(page.unwrap().len(), 1);
+    assert_eq!(btree.handle_count(), 2);
+    drop(iter);
+    assert_eq!(btree.handle_count(), 1);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Session {
+    user: String,
+    active: bool,
+}
+
+#[tokio::test]
+async fn test_generic_btree() {
+    let btree = BTree::<u64, Session>::spawn(1000);
+
+    let mut watch = btree.watch_range(10..20).await.unwrap();
+
+    let session = Session {
+        user: "julia".to_string(),
+        active: true,
+    };
+    let ins = btree.insert(15, session.clone()).await;
+    assert!(ins.unwrap().is_none());
+    let ins = btree.insert(25, session.clone()).await;
+    assert!(ins.unwrap().is_none());
+
+    let get = btree.get(15).await;
+    assert_eq!(get.unwrap(), Some(session.clone()));
+    assert_eq!(btree.keys().await.unwrap(), vec![15, 25]);
+
+    let event = match watch.recv().await {
+        Some(Event::Change(change)) => change,
+        e => panic!("expected a change event, got {:?}", e),
+    };
+    assert_eq!(event.key, 15);
+    assert_eq!(event.new, Some(session));
+}
+
+#[tokio::test]
+async fn test_batch() {
+    let btree = BTree::start(1000);
+
+    let ins = btree.insert("hello".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+
+    let mut changes = btree.changes().await.unwrap();
+
+    let batch = Batch::new()
+        .insert("wow".to_string(), 7)
+        .get_mut("hello".to_string(), 5, Operation::Add)
+        .remove("wow".to_string());
+    assert_eq!(btree.apply(batch).await, Ok(()));
+
+    let get = btree.get("hello".to_string()).await;
+    assert_eq!(get.unwrap(), Some(Types::Integer(10)));
+    assert!(!btree.contains("wow".to_string()).await.unwrap());
+
+    let batch = match changes.recv().await {
+        Some(Event::Batch(batch)) => batch,
+        e => panic!("expected a batch event, got {:?}", e),
+    };
+    let kinds: Vec<_> = batch.into_iter().map(|e| (e.key, e.kind)).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("wow".to_string(), ChangeKind::Insert),
+            ("hello".to_string(), ChangeKind::Update),
+            ("wow".to_string(), ChangeKind::Remove),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_batch_rollback() {
+    let btree = BTree::start(1000);
+
+    let ins = btree.insert("hello".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+
+    let mut changes = btree.changes().await.unwrap();
+
+    let batch = Batch::new()
+        .insert("wow".to_string(), 7)
+        .insert("hello".to_string(), 6)
+        .get_mut("missing".to_string(), 5, Operation::Add);
+    assert_eq!(btree.apply(batch).await, Err(Error::NotFound));
+
+    assert_eq!(btree.keys().await.unwrap(), vec!["hello".to_string()]);
+    let get = btree.get("hello".to_string()).await;
+    assert_eq!(get.unwrap(), Some(Types::Integer(5)));
+
+    let batch = Batch::new().insert("wow".to_string(), 7).get_mut(
+        "hello".to_string(),
+        isize::MAX,
+        Operation::Add,
+    );
+    assert_eq!(
+        btree.apply(batch).await,
+        Err(Error::Overflow { operation: "Add" })
+    );
+    assert!(!btree.contains("wow".to_string()).await.unwrap());
+
+    let ins = btree.insert("after".to_string(), 1).await;
+    assert!(ins.unwrap().is_none());
+    assert_eq!(change(changes.recv().await).key, "after".to_string());
+}
+
+#[tokio::test]
+async fn test_compare_and_swap() {
+    let btree = BTree::start(1000);
+
+    let cas = btree
+        .compare_and_swap("hello".to_string(), None, Some(Types::Integer(5)))
+        .await;
+    assert_eq!(cas.unwrap(), (true, Some(Types::Integer(5))));
+
+    let cas = btree
+        .compare_and_swap(
+            "hello".to_string(),
+            Some(Types::Integer(4)),
+            Some(Types::Integer(6)),
+        )
+        .await;
+    assert_eq!(cas.unwrap(), (false, Some(Types::Integer(5))));
+
+    let cas = btree
+        .compare_and_swap(
+            "hello".to_string(),
+            Some(Types::Integer(5)),
+            Some(Types::Integer(6)),
+        )
+        .await;
+    assert_eq!(cas.unwrap(), (true, Some(Types::Integer(6))));
+
+    let cas = btree
+        .compare_and_swap("hello".to_string(), Some(Types::Integer(6)), None)
+        .await;
+    assert_eq!(cas.unwrap(), (true, None));
+    assert!(!btree.contains("hello".to_string()).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_entry() {
+    let btree = BTree::<String, u64>::spawn(1000);
+
+    let mut tasks = Vec::new();
+    for _ in 0..10 {
+        let btree = btree.clone();
+        tasks.push(tokio::spawn(async move {
+            btree
+                .entry("counter".to_string())
+                .and_modify(|v| *v += 1)
+                .or_insert(1u64)
+                .await
+                .unwrap()
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let get = btree.get("counter".to_string()).await;
+    assert_eq!(get.unwrap(), Some(10));
+
+    let default = btree.entry("default".to_string()).or_default().await;
+    assert_eq!(default.unwrap(), 0);
+
+    let with = btree
+        .entry("default".to_string())
+        .or_insert_with(|| 5)
+        .await;
+    assert_eq!(with.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_entry_and_apply() {
+    let btree = BTree::start(1000);
+
+    for _ in 0..3 {
+        let _ = btree
+            .entry("counter".to_string())
+            .and_apply(1, Operation::Add)
+            .or_insert(1)
+            .await
+            .unwrap();
+    }
+
+    let get = btree.get("counter".to_string()).await;
+    assert_eq!(get.unwrap(), Some(Types::Integer(3)));
+
+    btree.insert("max".to_string(), isize::MAX).await.unwrap();
+    let overflow = btree
+        .entry("max".to_string())
+        .and_apply(1, Operation::Add)
+        .or_insert(1)
+        .await;
+    assert_eq!(overflow, Err(Error::Overflow { operation: "Add" }));
+    let get = btree.get("max".to_string()).await;
+    assert_eq!(get.unwrap(), Some(Types::Integer(isize::MAX)));
+
+    let unsupported = btree
+        .entry("counter".to_string())
+        .and_apply("x", Operation::Add)
+        .or_insert(1)
+        .await;
+    assert!(matches!(unsupported, Err(Error::Unsupported { .. })));
+}
+
+#[tokio::test]
+async fn test_range() {
+    let btree = BTree::<u64, u64>::spawn(1000);
+
+    for i in 0..10 {
+        let ins = btree.insert(i, i * 10).await;
+        assert!(ins.unwrap().is_none());
+    }
+
+    let range = btree.range(3..5).await;
+    assert_eq!(range.unwrap(), vec![(3, 30), (4, 40)]);
+
+    let range = btree.range(8..).await;
+    assert_eq!(range.unwrap(), vec![(8, 80), (9, 90)]);
+
+    let range = btree.range(..=1).await;
+    assert_eq!(range.unwrap(), vec![(0, 0), (1, 10)]);
+
+    #[allow(clippy::reversed_empty_ranges)]
+    let range = btree.range(5..3).await;
+    assert_eq!(range.unwrap(), vec![]);
+}
+
+#[tokio::test]
+async fn test_scan_prefix() {
+    let btree = BTree::start(1000);
+
+    btree.insert("tenant:1:a".to_string(), 1).await.unwrap();
+    btree.insert("tenant:1:b".to_string(), 2).await.unwrap();
+    btree.insert("tenant:10:a".to_string(), 3).await.unwrap();
+    btree.insert("tenant:2:a".to_string(), 4).await.unwrap();
+
+    let scan = btree.scan_prefix("tenant:1:").await.unwrap();
+    assert_eq!(
+        scan,
+        vec![
+            ("tenant:1:a".to_string(), Types::Integer(1)),
+            ("tenant:1:b".to_string(), Types::Integer(2)),
+        ]
+    );
+
+    let scan = btree.scan_prefix("tenant:").await.unwrap();
+    assert_eq!(scan.len(), 4);
+
+    let scan = btree.scan_prefix("user:").await.unwrap();
+    assert!(scan.is_empty());
+}
+
+#[tokio::test]
+async fn test_iter() {
+    let btree = BTree::<u64, u64>::spawn(1000);
+
+    for i in 0..10 {
+        btree.insert(i, i * 10).await.unwrap();
+    }
+
+    let entries: Vec<(u64, u64)> = btree.iter(3).map(|e| e.unwrap()).collect().await;
+    assert_eq!(entries, (0..10).map(|i| (i, i * 10)).collect::<Vec<_>>());
+
+    let mut iter = btree.iter_from(5, 3);
+    assert_eq!(
+        iter.next_page().await.unwrap(),
+        Some(vec![(5, 50), (6, 60), (7, 70)])
+    );
+    btree.insert(8, 1u64).await.unwrap();
+    assert_eq!(iter.next_page().await.unwrap(), Some(vec![(8, 1), (9, 90)]));
+    assert_eq!(iter.next_page().await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_first_last() {
+    let btree = BTree::<u64, String>::spawn(1000);
+    assert_eq!(btree.first_key_value().await.unwrap(), None);
+    assert_eq!(btree.pop_last().await.unwrap(), None);
+
+    for i in [30, 10, 20, 40] {
+        btree.insert(i, format!("job {}", i)).await.unwrap();
+    }
+
+    assert_eq!(
+        btree.first_key_value().await.unwrap(),
+        Some((10, "job 10".to_string()))
+    );
+    assert_eq!(
+        btree.last_key_value().await.unwrap(),
+        Some((40, "job 40".to_string()))
+    );
+    assert_eq!(btree.next_after(20).await.unwrap().unwrap().0, 30);
+    assert_eq!(btree.next_after(25).await.unwrap().unwrap().0, 30);
+    assert_eq!(btree.next_after(40).await.unwrap(), None);
+    assert_eq!(btree.prev_before(20).await.unwrap().unwrap().0, 10);
+    assert_eq!(btree.prev_before(10).await.unwrap(), None);
+
+    let mut events = btree.changes().await.unwrap();
+    assert_eq!(
+        btree.pop_first().await.unwrap(),
+        Some((10, "job 10".to_string()))
+    );
+    assert_eq!(
+        btree.pop_last().await.unwrap(),
+        Some((40, "job 40".to_string()))
+    );
+    assert_eq!(btree.keys().await.unwrap(), vec![20, 30]);
+
+    let event = change(events.recv().await);
+    assert_eq!((event.key, event.kind), (10, ChangeKind::Remove));
+}
+
+#[tokio::test]
+async fn test_bulk_mutation() {
+    let btree = BTree::<u64, u64>::spawn(1000);
+    let mut events = btree.changes().await.unwrap();
+
+    btree.extend((0..10).map(|i| (i, i))).await.unwrap();
+    assert_eq!(btree.len().await.unwrap(), 10);
+    match events.recv().await {
+        Some(Event::Batch(changes)) => assert_eq!(changes.len(), 10),
+        e => panic!("expected a batch event, got {:?}", e),
+    }
+
+    btree.retain(|_, v| v % 2 == 0).await.unwrap();
+    assert_eq!(btree.keys().await.unwrap(), vec![0, 2, 4, 6, 8]);
+
+    let drained = btree.drain_range(2..=6).await.unwrap();
+    assert_eq!(drained, vec![(2, 2), (4, 4), (6, 6)]);
+    assert_eq!(btree.keys().await.unwrap(), vec![0, 8]);
+
+    btree.clear().await.unwrap();
+    assert!(btree.is_empty().await.unwrap());
+
+    let removed: Vec<usize> = events
+        .take(3)
+        .map(|e| match e {
+            Event::Batch(changes) => {
+                assert!(changes.iter().all(|c| c.kind == ChangeKind::Remove));
+                changes.len()
+            }
+            e => panic!("expected a batch event, got {:?}", e),
+        })
+        .collect()
+        .await;
+    assert_eq!(removed, vec![5, 3, 2]);
+
+    btree.extend((0..4).map(|i| (i, i))).await.unwrap();
+    let retain = btree
+        .retain(|k, _| {
+            if *k == 2 {
+                panic!("bad entry")
+            } else {
+                *k != 0
+            }
+        })
+        .await;
+    assert_eq!(retain, Err(Error::Panicked));
+    assert_eq!(btree.keys().await.unwrap(), vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn test_start_with_into_inner() {
+    let mut map = BTreeMap::new();
+    map.insert("hello".to_string(), Types::Integer(5));
+    map.insert("wow".to_string(), Types::Integer(7));
+
+    let btree = BTree::start_with(map.clone(), 1000);
+    assert_eq!(btree.snapshot().await.unwrap(), map);
+
+    let ins = btree.insert("what?".to_string(), 3).await;
+    assert!(ins.unwrap().is_none());
+    let other = btree.clone();
+
+    let inner = btree.into_inner().await.unwrap();
+    assert_eq!(inner.len(), 3);
+    assert_eq!(inner.get("what?"), Some(&Types::Integer(3)));
+    assert_eq!(other.len().await, Err(Error::ActorStopped));
+
+    let btree: BTree<u64, u64> = BTree::from(BTreeMap::from([(1, 10), (2, 20)]));
+    assert_eq!(btree.keys().await.unwrap(), vec![1, 2]);
+}
+
+#[tokio::test]
+async fn test_get_mut_values() {
+    let btree = BTree::start(1000);
+
+    let ins = btree.insert("counter".to_string(), 5).await;
+    assert!(ins.unwrap().is_none());
+    let ins = btree.insert("flag".to_string(), true).await;
+    assert!(ins.unwrap().is_none());
+
+    let values = btree
+        .get_mut_values("counter".to_string(), 2, Operation::Add)
+        .await;
+    assert_eq!(
+        values.unwrap(),
+        Some((Types::Integer(5), Types::Integer(7)))
+    );
+
+    let new = btree
+        .get_mut_new("counter".to_string(), 3, Operation::Add)
+        .await;
+    assert_eq!(new.unwrap(), Some(Types::Integer(10)));
+
+    let old = btree
+        .get_mut_old("counter".to_string(), 0, Operation::Replace)
+        .await;
+    assert_eq!(old.unwrap(), Some(Types::Integer(10)));
+
+    let missing = btree
+        .get_mut_new("missing".to_string(), 1, Operation::Add)
+        .await;
+    assert_eq!(missing.unwrap(), None);
+
+    let unsupported = btree
+        .get_mut_new("flag".to_string(), 1, Operation::Add)
+        .await;
+    assert_eq!(
+        unsupported,
+        Err(Error::Unsupported {
+            operation: "Add",
+            actual: "Boolean"
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_arithmetic_operations() {
+    let btree = BTree::start(1000);
+
+    btree.insert("int".to_string(), 10).await.unwrap();
+    btree.insert("uint".to_string(), 10usize).await.unwrap();
+    btree.insert("float".to_string(), 1.5).await.unwrap();
+
+    let int = "int".to_string();
+    let ops = vec![
+        (3, Operation::Subtract, 7),
+        (4, Operation::Multiply, 28),
+        (5, Operation::Divide, 5),
+        (3, Operation::Modulo, 2),
+        (9, Operation::Max, 9),
+        (-1, Operation::Min, -1),
+        (0, Operation::Negate, 1),
+    ];
+    for (v, op, expected) in ops {
+        let new = btree.get_mut_new(int.clone(), v, op).await;
+        assert_eq!(new.unwrap(), Some(Types::Integer(expected)));
+    }
+
+    let new = btree
+        .get_mut_new("float".to_string(), 2.0, Operation::Multiply)
+        .await;
+    assert_eq!(new.unwrap(), Some(Types::Float(3.0)));
+
+    let new = btree
+        .get_mut_new("uint".to_string(), 3usize, Operation::Modulo)
+        .await;
+    assert_eq!(new.unwrap(), Some(Types::UInteger(1)));
+
+    // integer division by zero, unsigned underflow and unsigned negation fail without modifying the value
+    assert!(!btree
+        .get_mut(int.clone(), 0, Operation::Divide)
+        .await
+        .unwrap());
+    assert!(!btree
+        .get_mut("uint".to_string(), 2usize, Operation::Subtract)
+        .await
+        .unwrap());
+    assert!(!btree
+        .get_mut("uint".to_string(), 0usize, Operation::Negate)
+        .await
+        .unwrap());
+    assert_eq!(
+        btree.get("uint".to_string()).await.unwrap(),
+        Some(Types::UInteger(1))
+    );
+}
+
+#[tokio::test]
+async fn test_overflow() {
+    let btree = BTree::start(1000);
+
+    btree
+        .insert("counter".to_string(), isize::MAX)
+        .await
+        .unwrap();
+    btree.insert("gauge".to_string(), 1usize).await.unwrap();
+
+    let add = btree
+        .get_mut_new("counter".to_string(), 1, Operation::Add)
+        .await;
+    assert_eq!(add, Err(Error::Overflow { operation: "Add" }));
+    let add = btree
+        .get_mut("counter".to_string(), 1, Operation::Add)
+        .await;
+    assert!(!add.unwrap());
+
+    let add = btree
+        .get_mut_new("counter".to_string(), 1, Operation::SaturatingAdd)
+        .await;
+    assert_eq!(add.unwrap(), Some(Types::Integer(isize::MAX)));
+
+    let add = btree
+        .get_mut_new("counter".to_string(), 1, Operation::WrappingAdd)
+        .await;
+    assert_eq!(add.unwrap(), Some(Types::Integer(isize::MIN)));
+
+    let sub = btree
+        .get_mut_new("gauge".to_string(), 2usize, Operation::SaturatingSubtract)
+        .await;
+    assert_eq!(sub.unwrap(), Some(Types::UInteger(0)));
+
+    let div = btree
+        .get_mut_new("gauge".to_string(), 0usize, Operation::Divide)
+        .await;
+    assert_eq!(div, Err(Error::DivisionByZero));
+    let div = btree
+        .get_mut_new("counter".to_string(), -1, Operation::Divide)
+        .await;
+    assert_eq!(
+        div,
+        Err(Error::Overflow {
+            operation: "Divide"
+        })
+    );
+
+    btree.insert("text".to_string(), "hello").await.unwrap();
+    let modulo = btree
+        .get_mut_new("text".to_string(), 0, Operation::Modulo)
+        .await;
+    assert_eq!(
+        modulo,
+        Err(Error::Unsupported {
+            operation: "Modulo",
+            actual: "String"
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_collection_operations() {
+    let btree = BTree::start(1000);
+    let list = "list".to_string();
+
+    let ins = btree.insert(list.clone(), vec![3, 1, 2, 3, 1, 5, 4]).await;
+    assert!(ins.unwrap().is_none());
+
+    let ops = vec![
+        (Types::Nil, Operation::Dedup, vec![3, 1, 2, 5, 4]),
+        (Types::Nil, Operation::Sort, vec![1, 2, 3, 4, 5]),
+        (Types::Nil, Operation::PopFront, vec![2, 3, 4, 5]),
+        (Types::Nil, Operation::PopBack, vec![2, 3, 4]),
+        (Types::Integer(3), Operation::RemoveValue, vec![2, 4]),
+        (Types::UInteger(0), Operation::RemoveIndex, vec![4]),
+        (Types::UInteger(0), Operation::Truncate, vec![]),
+    ];
+    for (v, op, expected) in ops {
+        let new = btree.get_mut_new(list.clone(), v, op).await;
+        assert_eq!(new.unwrap(), Some(expected.into()));
+    }
+
+    let pop = btree
+        .get_mut_new(list.clone(), Types::Nil, Operation::PopBack)
+        .await;
+    assert_eq!(pop, Err(Error::NotFound));
+
+    let mut map = std::collections::HashMap::new();
+    map.insert("a".to_string(), Types::Integer(1));
+    btree
+        .insert("map".to_string(), Types::HashMap(map))
+        .await
+        .unwrap();
+    let remove = btree
+        .get_mut_new("map".to_string(), "a".to_string(), Operation::RemoveKey)
+        .await;
+    assert_eq!(
+        remove.unwrap(),
+        Some(Types::HashMap(std::collections::HashMap::new()))
+    );
+
+    btree
+        .insert("mixed".to_string(), vec![Types::Integer(1), Types::Nil])
+        .await
+        .unwrap();
+    let sort = btree
+        .get_mut_new("mixed".to_string(), Types::Nil, Operation::Sort)
+        .await;
+    assert_eq!(
+        sort,
+        Err(Error::Unsupported {
+            operation: "Sort",
+            actual: "Vector"
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_nested_path() {
+    let btree = BTree::start(1000);
+
+    let mut limits = std::collections::HashMap::new();
+    limits.insert("limits".to_string(), Types::from(vec![10, 20, 30, 40]));
+    let mut config = std::collections::HashMap::new();
+    config.insert("config".to_string(), Types::HashMap(limits));
+    btree
+        .insert("tenant".to_string(), Types::HashMap(config))
+        .await
+        .unwrap();
+
+    let get = btree
+        .get_path("tenant".to_string(), "/config/limits/3")
+        .await;
+    assert_eq!(get.unwrap(), Some(Types::Integer(40)));
+    let get = btree
+        .get_path("tenant".to_string(), "/config/missing")
+        .await;
+    assert_eq!(get.unwrap(), None);
+
+    let path = Path::new().key("config").key("limits").index(3);
+    let update = btree
+        .get_mut_path("tenant".to_string(), path.clone(), 2, Operation::Add)
+        .await;
+    assert_eq!(update.unwrap(), Some(Types::Integer(42)));
+
+    let replace = btree
+        .get_mut_path(
+            "tenant".to_string(),
+            "/config/name",
+            "acme",
+            Operation::Replace,
+        )
+        .await;
+    assert_eq!(replace.unwrap(), Some(Types::from("acme")));
+
+    let remove = btree
+        .remove_path("tenant".to_string(), "/config/limits/0")
+        .await;
+    assert_eq!(remove.unwrap(), Some(Types::Integer(10)));
+    let get = btree.get_path("tenant".to_string(), "/config/limits").await;
+    assert_eq!(get.unwrap(), Some(Types::from(vec![20, 30, 42])));
+
+    let out_of_bounds = btree
+        .get_mut_path("tenant".to_string(), path, 1, Operation::Add)
+        .await;
+    assert_eq!(
+        out_of_bounds,
+        Err(Error::IndexOutOfBounds { index: 3, len: 3 })
+    );
+    let missing = btree.remove_path("missing".to_string(), "/config").await;
+    assert_eq!(missing.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_update() {
+    let btree = BTree::start(1000);
+    let mut watch = btree.watch("stock".to_string()).await.unwrap();
+
+    let ins = btree.insert("stock".to_string(), 3).await;
+    assert!(ins.unwrap().is_none());
+
+    let reserve = |x: Option<&mut Types>| match x {
+        Some(Types::Integer(stock)) if *stock > 0 => {
+            *stock -= 1;
+            Ok(*stock)
+        }
+        _ => Err("out of stock"),
+    };
+    for expected in (0..3).rev() {
+        let update = btree.update("stock".to_string(), reserve).await;
+        assert_eq!(update.unwrap(), Ok(expected));
+    }
+    let update = btree.update("stock".to_string(), reserve).await;
+    assert_eq!(update.unwrap(), Err("out of stock"));
+
+    let missing = btree.update("missing".to_string(), |x| x.is_none()).await;
+    assert!(missing.unwrap());
+
+    let events: Vec<ChangeKind> = (0..4)
+        .map(|_| change(watch.recv().now_or_never().flatten()).kind)
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            ChangeKind::Insert,
+            ChangeKind::Update,
+            ChangeKind::Update,
+            ChangeKind::Update
+        ]
+    );
+    assert!(watch.recv().now_or_never().is_none());
+}
+
+#[tokio::test]
+async fn test_custom_operation() {
+    let append_line = |current: &mut Types, arg: Types| match (current, arg) {
+        (Types::String(text), Types::String(line)) => {
+            text.push('\n');
+            text.push_str(&line);
+            Ok(Types::UInteger(text.lines().count()))
+        }
+        (current, _) => Err(Error::Custom(format!(
+            "can't append a line to {}",
+            current.type_name()
+        ))),
+    };
+    let operations = Registry::new().register("append_line", append_line);
+    let btree = BTree::start_with_operations(operations, 1000);
+
+    btree.insert("log".to_string(), "start").await.unwrap();
+    btree.insert("count".to_string(), 1).await.unwrap();
+
+    let new = btree
+        .get_mut_new("log".to_string(), "stop", Operation::custom("append_line"))
+        .await;
+    assert_eq!(new.unwrap(), Some(Types::from("start\nstop")));
+
+    let lines = btree
+        .get_mut_custom("log".to_string(), "append_line", "restart")
+        .await;
+    assert_eq!(lines.unwrap(), Some(Types::UInteger(3)));
+
+    let batch = Batch::new()
+        .get_mut("log".to_string(), "end", Operation::custom("append_line"))
+        .get_mut("count".to_string(), "end", Operation::custom("append_line"));
+    assert_eq!(
+        btree.apply(batch).await,
+        Err(Error::Custom("can't append a line to Integer".to_string()))
+    );
+
+    let unknown = btree
+        .get_mut_new("log".to_string(), "end", Operation::custom("prepend_line"))
+        .await;
+    assert_eq!(
+        unknown,
+        Err(Error::UnknownOperation("prepend_line".to_string()))
+    );
+    let get = btree.get("log".to_string()).await;
+    assert_eq!(get.unwrap(), Some(Types::from("start\nstop\nrestart")));
+
+    let missing = btree
+        .get_mut_custom("missing".to_string(), "append_line", "end")
+        .await;
+    assert_eq!(missing.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_custom_operation_panic() {
+    let clear_then_panic = |current: &mut Types, _: Types| -> Result<Types, Error> {
+        *current = Types::Nil;
+        panic!("custom operation bug")
+    };
+    let operations = Registry::new().register("clear_then_panic", clear_then_panic);
+    let btree = BTree::start_with_operations(operations, 1000);
+
+    btree.insert("log".to_string(), "start").await.unwrap();
+    let new = btree
+        .get_mut_new(
+            "log".to_string(),
+            Types::Nil,
+            Operation::custom("clear_then_panic"),
+        )
+        .await;
+    assert_eq!(new, Err(Error::Panicked));
+
+    let get = btree.get("log".to_string()).await;
+    assert_eq!(get.unwrap(), Some(Types::from("start")));
+}
+
+static CLONES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[derive(Debug, PartialEq)]
+struct Counted(u64);
+
+impl Clone for Counted {
+    fn clone(&self) -> Self {
+        CLONES.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Counted(self.0)
+    }
+}
+
+#[tokio::test]
+async fn test_unwatched_changes_are_not_copied() {
+    let btree = BTree::<u64, Counted>::spawn(1000);
+    btree.insert(1, Counted(1)).await.unwrap();
+
+    let clones = CLONES.load(std::sync::atomic::Ordering::SeqCst);
+    let entry = btree
+        .entry(1)
+        .and_modify(|c| c.0 += 1)
+        .or_insert(Counted(0));
+    assert_eq!(entry.await.unwrap(), Counted(2));
+    // only the value returned by the entry is copied
+    assert_eq!(CLONES.load(std::sync::atomic::Ordering::SeqCst), clones + 1);
+
+    // bulk mutations don't copy anything when no key is watched
+    btree
+        .extend((10..20).map(|i| (i, Counted(i))))
+        .await
+        .unwrap();
+    btree.retain(|k, _| k % 2 == 1).await.unwrap();
+    let drained = btree.drain_range(10..15).await.unwrap();
+    assert_eq!(drained.len(), 2);
+    btree.clear().await.unwrap();
+    assert_eq!(CLONES.load(std::sync::atomic::Ordering::SeqCst), clones + 1);
+    btree.insert(1, Counted(2)).await.unwrap();
+
+    let mut watch = btree.watch(1).await.unwrap();
+    let entry = btree
+        .entry(1)
+        .and_modify(|c| c.0 += 1)
+        .or_insert(Counted(0));
+    assert_eq!(entry.await.unwrap(), Counted(3));
+    let event = change(watch.recv().await);
+    assert_eq!(event.old, Some(Counted(2)));
+    assert_eq!(event.new, Some(Counted(3)));
+}
+
+#[tokio::test]
+async fn test_update_panic_and_nan() {
+    let btree = BTree::<String, f64>::spawn(1000);
+    let mut watch = btree.watch("gauge".to_string()).await.unwrap();
+
+    btree.insert("gauge".to_string(), f64::NAN).await.unwrap();
+    assert_eq!(change(watch.recv().await).kind, ChangeKind::Insert);
+
+    // `NaN` is not equal to itself, so it is reported as changed rather than missing a change.
+    let update = btree.update("gauge".to_string(), |_| ()).await;
+    assert_eq!(update, Ok(()));
+    assert_eq!(change(watch.recv().await).kind, ChangeKind::Update);
+
+    let update: Result<(), Error> = btree
+        .update("gauge".to_string(), |_| panic!("business rule"))
+        .await;
+    assert_eq!(update, Err(Error::Panicked));
+    assert_eq!(change(watch.recv().await).kind, ChangeKind::Update);
+
+    let update = btree
+        .update("gauge".to_string(), |x| *x.unwrap() = 1.0)
+        .await;
+    assert_eq!(update, Ok(()));
+    assert_eq!(change(watch.recv().await).new, Some(1.0));
+    assert_eq!(btree.len().await, Ok(1));
+
+    let btree = BTree::start(1000);
+    let mut watch = btree.watch("samples".to_string()).await.unwrap();
+    let samples = Types::Vector(vec![Types::Float(f64::NAN), Types::Integer(1)]);
+    btree.insert("samples".to_string(), samples).await.unwrap();
+    assert_eq!(change(watch.recv().await).kind, ChangeKind::Insert);
+
+    let update = btree
+        .update("samples".to_string(), |x| {
+            if let Some(Types::Vector(samples)) = x {
+                samples[1] = Types::Integer(2);
+            }
+        })
+        .await;
+    assert_eq!(update, Ok(()));
+    let event = change(watch.recv().await);
+    assert_eq!(event.kind, ChangeKind::Update);
+    match event.new {
+        Some(Types::Vector(samples)) => assert_eq!(samples[1], Types::Integer(2)),
+        new => panic!("expected a vector, got {:?}", new),
+    }
+}
+
+#[tokio::test]
+async fn test_entry_panic() {
+    let btree = BTree::<u64, u64>::spawn(1000);
+
+    let entry = btree.entry(1).or_insert_with(|| panic!("no default"));
+    assert_eq!(entry.await, Err(Error::Panicked));
+    assert!(!btree.contains(1).await.unwrap());
+
+    btree.insert(1, 5u64).await.unwrap();
+    let entry = btree
+        .entry(1)
+        .and_modify(|_| panic!("bad update"))
+        .or_insert(0u64);
+    assert_eq!(entry.await, Err(Error::Panicked));
+    assert_eq!(btree.get(1).await, Ok(Some(5)));
+}